@@ -2,8 +2,8 @@ use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 use std::env::current_dir;
 use std::fs::create_dir_all;
 
-use EscrowNFT::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
-use EscrowNFT::state::State;
+use escrow_nft::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
+use escrow_nft::state::State;
 
 fn main() {
     let mut out_dir = current_dir().unwrap();
@@ -1,19 +1,34 @@
 use cosmwasm_std::{
-    entry_point, to_binary, from_binary, Coin, DepsMut, Env, MessageInfo, Response,
-    StdResult, Uint128, CosmosMsg, WasmMsg, BankMsg, Storage
+    entry_point, to_binary, to_vec, from_binary, Addr, Api, Binary, BlockInfo, Coin, DepsMut, Env, Event, MessageInfo, Reply, Response,
+    StdError, StdResult, SubMsg, SubMsgResult, Timestamp, Uint128, CosmosMsg, WasmMsg, BankMsg, Storage, Deps, Empty, QueryRequest, WasmQuery
 };
 
 use cw2::set_contract_version;
-use cw721::{Cw721ReceiveMsg, Cw721ExecuteMsg};
+use cw_storage_plus::Map;
+use cw721::{Cw721ReceiveMsg, Cw721ExecuteMsg, Cw721QueryMsg, Expiration, OwnerOfResponse};
+use sha2::{Digest, Sha256};
 
-use crate::msg::{ ExecuteMsg, InstantiateMsg,  EscrowInfoMsg};
-use crate::state::{ escrows, escrow_key,Order, Escrow, State, CONFIG };
+use crate::msg::{ ExecuteMsg, InstantiateMsg,  EscrowInfoMsg, EscrowHookMsg, ReceiveNftMsg, SignedOffer};
+use crate::state::{ escrows, escrow_key,Order, Escrow, State, Settlement, TradingStats, CONFIG,
+    COLLECTION_STATS, CONTRACT_STATS, ESCROW_SEQ, HOOKS, PENDING_SETTLEMENTS, SOURCE_COUNTS, RECIPIENT_COUNTS,
+    OPERATORS, PAYEE_ESCROWS, PUBKEYS, USED_NONCES, REVEALED_PREIMAGES, BIDS, SEALED_BIDS, Bid, SealedBid, EscrowKind, DecayCurve, SealedBidPricing,
+    Payee, Condition, LockedPayment, Offer, offers, CollectionOffer, collection_offers, OFFER_SEQ, Milestone, MilestoneStatus, sealed_bid_commitment, TOTAL_SHARE_BPS };
 use crate::error::ContractError;
 
 
 const CONTRACT_NAME: &str = "Escrow Contract";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
+// Reply ids
+pub const HOOK_REPLY_ID: u64 = 1;
+/// Settlement replies carry the escrow id on top of this base
+pub const SETTLE_REPLY_ID_BASE: u64 = 1 << 32;
+
+/// Reply id of the NFT transfer settling an escrow
+pub fn settle_reply_id(escrow_id: u64) -> u64 {
+    SETTLE_REPLY_ID_BASE + escrow_id
+}
+
 #[entry_point]
 pub fn instantiate(
     deps: DepsMut,
@@ -60,14 +75,256 @@ pub fn execute(
         ),
         ExecuteMsg::Approve { 
             collection, 
-            token_id 
+            token_id,
+            deliver_to,
+            deliver_msg
         } => execute_approve(
+            deps,
+            env,
+            info,
+            collection,
+            token_id,
+            deliver_to,
+            deliver_msg
+        ),
+        ExecuteMsg::Release {
+            collection,
+            token_id
+        } => execute_release(
+            deps,
+            env,
+            info,
+            collection,
+            token_id
+        ),
+        ExecuteMsg::AuthorizePayer {
+            collection,
+            token_id,
+            payer,
+            deliver_to,
+            deliver_msg
+        } => execute_authorize_payer(
+            deps,
+            env,
+            info,
+            collection,
+            token_id,
+            payer,
+            deliver_to,
+            deliver_msg
+        ),
+        ExecuteMsg::ClaimHtlc {
+            collection,
+            token_id,
+            preimage
+        } => execute_claim_htlc(
+            deps,
+            env,
+            info,
+            collection,
+            token_id,
+            preimage
+        ),
+        ExecuteMsg::Bid {
+            collection,
+            token_id
+        } => execute_bid(
+            deps,
+            env,
+            info,
+            collection,
+            token_id
+        ),
+        ExecuteMsg::CommitBid {
+            collection,
+            token_id,
+            commitment
+        } => execute_commit_bid(
+            deps,
+            env,
+            info,
+            collection,
+            token_id,
+            commitment
+        ),
+        ExecuteMsg::RevealBid {
+            collection,
+            token_id,
+            amount,
+            salt
+        } => execute_reveal_bid(
+            deps,
+            env,
+            info,
+            collection,
+            token_id,
+            amount,
+            salt
+        ),
+        ExecuteMsg::Settle {
+            collection,
+            token_id
+        } => execute_settle(
+            deps,
+            env,
+            info,
+            collection,
+            token_id
+        ),
+        ExecuteMsg::SubmitMilestone {
+            collection,
+            token_id,
+            milestone,
+            work
+        } => execute_submit_milestone(
+            deps,
+            env,
+            info,
+            collection,
+            token_id,
+            milestone,
+            work
+        ),
+        ExecuteMsg::ApproveMilestone {
+            collection,
+            token_id,
+            milestone
+        } => execute_approve_milestone(
+            deps,
+            env,
+            info,
+            collection,
+            token_id,
+            milestone
+        ),
+        ExecuteMsg::DisputeMilestone {
+            collection,
+            token_id,
+            milestone
+        } => execute_dispute_milestone(
+            deps,
+            env,
+            info,
+            collection,
+            token_id,
+            milestone
+        ),
+        ExecuteMsg::ResolveDispute {
+            collection,
+            token_id,
+            milestone,
+            release
+        } => execute_resolve_dispute(
+            deps,
+            env,
+            info,
+            collection,
+            token_id,
+            milestone,
+            release
+        ),
+        ExecuteMsg::Cancel {
+            collection,
+            token_id
+        } => execute_cancel(
             deps,
             env,
             info,
             collection,
             token_id
         ),
+        ExecuteMsg::UpdateEscrow {
+            collection,
+            token_id,
+            recipient,
+            price,
+            expiration
+        } => execute_update_escrow(
+            deps,
+            env,
+            info,
+            collection,
+            token_id,
+            recipient,
+            price,
+            expiration
+        ),
+        ExecuteMsg::ApproveAll {
+            operator,
+            expires
+        } => execute_approve_all(
+            deps,
+            env,
+            info,
+            operator,
+            expires
+        ),
+        ExecuteMsg::RevokeAll {
+            operator
+        } => execute_revoke_all(
+            deps,
+            info,
+            operator
+        ),
+        ExecuteMsg::RegisterPubkey {
+            pubkey
+        } => execute_register_pubkey(
+            deps,
+            info,
+            pubkey
+        ),
+        ExecuteMsg::FulfillSignedOffer {
+            offer,
+            signature
+        } => execute_fulfill_signed_offer(
+            deps,
+            env,
+            info,
+            offer,
+            signature
+        ),
+        ExecuteMsg::MakeOffer {
+            collection,
+            token_id,
+            expiration
+        } => execute_make_offer(
+            deps,
+            env,
+            info,
+            collection,
+            token_id,
+            expiration
+        ),
+        ExecuteMsg::WithdrawOffer {
+            offer_id
+        } => execute_withdraw_offer(
+            deps,
+            env,
+            info,
+            offer_id
+        ),
+        ExecuteMsg::MakeCollectionOffer {
+            collection,
+            price,
+            quantity,
+            expiration
+        } => execute_make_collection_offer(
+            deps,
+            env,
+            info,
+            collection,
+            price,
+            quantity,
+            expiration
+        ),
+        ExecuteMsg::WithdrawCollectionOffer {
+            offer_id
+        } => execute_withdraw_collection_offer(
+            deps,
+            env,
+            info,
+            offer_id
+        ),
         ExecuteMsg::ChangeConfig { 
             state 
         } => execute_change_config(
@@ -75,6 +332,20 @@ pub fn execute(
             env,
             info,
             state
+        ),
+        ExecuteMsg::AddHook { 
+            addr 
+        } => execute_add_hook(
+            deps,
+            info,
+            addr
+        ),
+        ExecuteMsg::RemoveHook { 
+            addr 
+        } => execute_remove_hook(
+            deps,
+            info,
+            addr
         )
             
  }
@@ -88,24 +359,51 @@ fn execute_receive_nft(
     rcv_msg: Cw721ReceiveMsg,
 )-> Result<Response, ContractError> {
     
+    //the NFT may be sold to an offer instead of opening an escrow
+    match from_binary(&rcv_msg.msg) {
+        Ok(ReceiveNftMsg::AcceptOffer { offer_id }) => return execute_accept_offer(deps, env, info, rcv_msg, offer_id),
+        Ok(ReceiveNftMsg::FillCollectionOffer { offer_id }) => return execute_fill_collection_offer(deps, env, info, rcv_msg, offer_id),
+        Err(_) => {}
+    }
+
     let msg:EscrowInfoMsg = from_binary(&rcv_msg.msg)?;
     let collection = info.sender.to_string();
     let token_id = rcv_msg.token_id.clone();
 
     //validation check
-    deps.api.addr_validate(&msg.recipient)?;
-    if msg.price == Uint128::zero() {
-        return Err(ContractError::NotEnoughFunds {  })
+    //auctions may leave the recipient open to any buyer
+    if !(msg.recipient.is_empty() && msg.kind.is_auction()) {
+        deps.api.addr_validate(&msg.recipient)?;
     }
+    validate_terms(&msg.recipient, &msg.price, &msg.kind, msg.expiration)?;
+    if let Some(condition) = &msg.condition {
+        //only escrows settled in one go by `Approve` can wait for a condition
+        if !msg.kind.is_approvable() || matches!(msg.kind, EscrowKind::Milestones { .. }) {
+            return Err(ContractError::WrongEscrowKind {  })
+        }
+        deps.api.addr_validate(&condition.contract)?;
+    }
+    if let EscrowKind::Milestones { arbiter, .. } = &msg.kind {
+        deps.api.addr_validate(arbiter)?;
+    }
+    validate_payees(deps.api, &msg.payees)?;
 
     //Save escrow information
     let escrow = Escrow {
+        id: next_escrow_id(deps.storage)?,
         source: rcv_msg.sender.clone(),
         recipient: msg.recipient,
         price: msg.price,
         expires_at: msg.expiration,
         collection,
         token_id,
+        payer: None,
+        payer_deliver_to: None,
+        payer_deliver_msg: None,
+        payees: msg.payees,
+        kind: msg.kind,
+        condition: msg.condition,
+        payment: None,
     };
 
     //check if this escrow is expired because of the wrong setting
@@ -115,10 +413,12 @@ fn execute_receive_nft(
 
     store_escrow(deps.storage, &escrow)?;
 
+    let hooks = prepare_escrow_hooks(deps.storage, EscrowHookMsg::EscrowCreated { escrow: escrow.clone() })?;
+
     Ok(Response::new()
-        .add_attribute("action", "Send nfts to the arbiter contract")
-        .add_attribute("token_id", rcv_msg.token_id)
-        .add_attribute("source_user", rcv_msg.sender))
+        .add_attribute("action", "receive_nft")
+        .add_event(escrow_event("escrow_create", &escrow))
+        .add_submessages(hooks))
 }
 
 
@@ -137,46 +437,31 @@ fn execute_withdraw(
     let sender = info.sender.to_string();
     
     //load escrow
-    let escrow_key = escrow_key(&collection.clone(), &token_id.clone());
-    let escrow = escrows().may_load(deps.storage, escrow_key.clone())?;
-    
-    match escrow {
-        Some(escrow) => {
-            //User can withdraw after the escrow is expired
-            if !escrow.is_expired(&env.block){
-                return Err(ContractError::EscrowNotExpired {  })
-            }
-            if sender != escrow.source{
-                return Err(ContractError::Unauthorized {  } )
-            }
-            //remove current escrow 
-            remove_escrow(deps.storage, &escrow)?;
-        },
-        None => {
-            return Err(ContractError::NoEscrow{} );
-        }
-    }
+    let escrow = load_escrow(deps.storage, &collection, &token_id)?;
 
-    let cw721_transfer_msg = WasmMsg::Execute { 
-        contract_addr: collection.clone(), 
-        msg: to_binary(&Cw721ExecuteMsg::TransferNft { recipient: sender.clone(), token_id: token_id.clone() })? , 
-        funds: vec![] 
-    };
+    withdraw_check(deps.storage, &env.block, &escrow, &sender)?;
+
+    //remove current escrow 
+    remove_escrow(deps.storage, &escrow)?;
+
+    //the NFT always goes back to the source, even when an operator withdraws
+    let cw721_transfer_msg = nft_transfer_msg(&collection, &escrow.source, &token_id, None)?;
 
     let message: CosmosMsg = CosmosMsg::Wasm(cw721_transfer_msg);
+    let hooks = prepare_escrow_hooks(deps.storage, EscrowHookMsg::EscrowWithdrawn { escrow: escrow.clone() })?;
 
     Ok(Response::new()
-        .add_attribute("action", "Withdraw NFT")
-        .add_attribute("token_id", token_id)
-        .add_attribute("collection", collection)
-        .add_attribute("source", sender)
+        .add_attribute("action", "withdraw_nft")
+        .add_event(escrow_event("escrow_withdraw", &escrow))
         .add_message(message)
+        .add_messages(payment_refund(&escrow))
+        .add_submessages(hooks)
       )
 }
 
 
 
-fn execute_approve(
+fn execute_cancel(
     deps: DepsMut,
     env:Env,
     info: MessageInfo,
@@ -186,122 +471,1764 @@ fn execute_approve(
     
     //validation check
     deps.api.addr_validate(&collection)?;
+    nonpayable(&info)?;
 
-    let sender = info.sender.to_string();
-    
     //load escrow
-    let escrow_key = escrow_key(&collection.clone(), &token_id.clone());
-    let escrow = escrows().may_load(deps.storage, escrow_key.clone())?;
+    let escrow = load_escrow(deps.storage, &collection, &token_id)?;
     let state = CONFIG.load(deps.storage)?;
 
-    let mut messages :Vec<CosmosMsg> = Vec::new();
+    cancel_check(deps.storage, &state, &env.block, &escrow, info.sender.as_str())?;
 
-    match escrow {
-        Some(escrow) => {
-            //User can withdraw after the escrow is expired
-            if escrow.is_expired(&env.block){
-                return Err(ContractError::EscrowExpired {  })
-            }
-            //Check if the sent money is the same as the list price of escrow
-            fund_check(&state, &info, &escrow)?;
-          
-            if sender != escrow.recipient {
-                return Err(ContractError::Unauthorized {  } )
-            }
-            //remove current escrow 
-            remove_escrow(deps.storage, &escrow)?;
+    //remove current escrow and hand the NFT back to its source
+    remove_escrow(deps.storage, &escrow)?;
 
-            let transfer_coin_msg = BankMsg::Send { 
-                to_address: escrow.source, 
-                amount:  vec![Coin{denom: state.denom, amount: escrow.price}]
-            };
-            messages.push(CosmosMsg::Bank(transfer_coin_msg) );
-        },
-        None => {
-            return Err(ContractError::NoEscrow{} );
-        }
+    let cw721_transfer_msg = nft_transfer_msg(&collection, &escrow.source, &token_id, None)?;
+    let hooks = prepare_escrow_hooks(deps.storage, EscrowHookMsg::EscrowCancelled { escrow: escrow.clone() })?;
+
+    Ok(Response::new()
+        .add_attribute("action", "cancel")
+        .add_event(escrow_event("escrow_cancel", &escrow))
+        .add_message(cw721_transfer_msg)
+        .add_messages(payment_refund(&escrow))
+        .add_submessages(hooks)
+      )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn execute_update_escrow(
+    deps: DepsMut,
+    env:Env,
+    info: MessageInfo,
+    collection: String,
+    token_id: String,
+    recipient: Option<String>,
+    price: Option<Vec<Coin>>,
+    expiration: Option<Timestamp>
+)-> Result<Response, ContractError> {
+    
+    //validation check
+    deps.api.addr_validate(&collection)?;
+    nonpayable(&info)?;
+
+    //load escrow
+    let mut escrow = load_escrow(deps.storage, &collection, &token_id)?;
+
+    //counterparties of other kinds rely on the terms staying as they are
+    if escrow.kind != EscrowKind::Standard {
+        return Err(ContractError::WrongEscrowKind {  })
+    }
+    if escrow.payment.is_some() {
+        return Err(ContractError::AlreadyPaid {  })
     }
+    source_or_operator(deps.storage, &env.block, &escrow, info.sender.as_str())?;
 
-    let cw721_transfer_msg = WasmMsg::Execute { 
-        contract_addr: collection.clone(), 
-        msg: to_binary(&Cw721ExecuteMsg::TransferNft { recipient: sender.clone(), token_id: token_id.clone() })? , 
-        funds: vec![] 
-    };
+    if let Some(recipient) = recipient {
+        deps.api.addr_validate(&recipient)?;
+        //a payer authorized by the previous recipient no longer applies
+        if recipient != escrow.recipient {
+            escrow.payer = None;
+            escrow.payer_deliver_to = None;
+            escrow.payer_deliver_msg = None;
+        }
+        escrow.recipient = recipient;
+    }
+    if let Some(price) = price {
+        validate_price(&price)?;
+        escrow.price = price;
+    }
+    if let Some(expiration) = expiration {
+        escrow.expires_at = expiration;
+    }
+    if escrow.is_expired(&env.block){
+        return Err(ContractError::EscrowExpired {  })
+    }
 
-    messages.push(CosmosMsg::Wasm(cw721_transfer_msg));
+    store_escrow(deps.storage, &escrow)?;
 
     Ok(Response::new()
-        .add_attribute("action", "Withdraw NFT")
-        .add_attribute("token_id", token_id)
-        .add_attribute("collection", collection)
-        .add_attribute("source", sender)
-        .add_messages(messages)
+        .add_attribute("action", "update_escrow")
+        .add_event(escrow_event("escrow_update", &escrow))
       )
 }
 
-fn execute_change_config(
+fn execute_approve_all(
     deps: DepsMut,
-    _env: Env,
+    env:Env,
     info: MessageInfo,
-    state: State
-) -> Result<Response, ContractError> {
-    only_owner(&state, &info)?;
+    operator: String,
+    expires: Option<Expiration>
+)-> Result<Response, ContractError> {
+    nonpayable(&info)?;
 
-    CONFIG.save(deps.storage, &state)?;
+    //reject expired data as invalid
+    let expires = expires.unwrap_or_default();
+    if expires.is_expired(&env.block) {
+        return Err(ContractError::Expired {  })
+    }
+
+    let operator_addr = deps.api.addr_validate(&operator)?;
+    OPERATORS.save(deps.storage, (&info.sender, &operator_addr), &expires)?;
 
     Ok(Response::new()
-        .add_attribute("action", "chanage config")
+        .add_attribute("action", "approve_all")
+        .add_attribute("sender", info.sender)
+        .add_attribute("operator", operator)
       )
-
 }
 
+fn execute_revoke_all(
+    deps: DepsMut,
+    info: MessageInfo,
+    operator: String
+)-> Result<Response, ContractError> {
+    nonpayable(&info)?;
 
+    let operator_addr = deps.api.addr_validate(&operator)?;
+    OPERATORS.remove(deps.storage, (&info.sender, &operator_addr));
 
-fn store_escrow(store: &mut dyn Storage, escrow: &Escrow) -> StdResult<()> {
-    escrows().save(store, escrow_key(&escrow.collection, &escrow.token_id), escrow)
+    Ok(Response::new()
+        .add_attribute("action", "revoke_all")
+        .add_attribute("sender", info.sender)
+        .add_attribute("operator", operator)
+      )
 }
 
+#[allow(clippy::too_many_arguments)]
+fn execute_authorize_payer(
+    deps: DepsMut,
+    env:Env,
+    info: MessageInfo,
+    collection: String,
+    token_id: String,
+    payer: Option<String>,
+    deliver_to: Option<String>,
+    deliver_msg: Option<Binary>
+)-> Result<Response, ContractError> {
+    
+    //validation check
+    deps.api.addr_validate(&collection)?;
+    nonpayable(&info)?;
+
+    //load escrow
+    let mut escrow = load_escrow(deps.storage, &collection, &token_id)?;
 
-fn remove_escrow(store: &mut dyn Storage, escrow: &Escrow) -> StdResult<()> {
-    escrows().remove(store, escrow_key(&escrow.collection, &escrow.token_id))
+    //only the recipient decides who may pay on its behalf
+    if info.sender != escrow.recipient {
+        return Err(ContractError::Unauthorized {  } )
+    }
+    if escrow.is_expired(&env.block){
+        return Err(ContractError::EscrowExpired {  })
+    }
+    if let Some(payer) = &payer {
+        deps.api.addr_validate(payer)?;
+    }
+    if let Some(deliver_to) = &deliver_to {
+        deps.api.addr_validate(deliver_to)?;
+    }
+    escrow.payer = payer;
+    escrow.payer_deliver_to = deliver_to;
+    escrow.payer_deliver_msg = deliver_msg;
+    store_escrow(deps.storage, &escrow)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "authorize_payer")
+        .add_event(escrow_event("escrow_authorize_payer", &escrow)
+            .add_attribute("payer", escrow.payer.clone().unwrap_or_default())
+            .add_attribute("deliver_to", escrow.payer_deliver_to.clone().unwrap_or_default()))
+      )
 }
 
-fn fund_check(state: &State, info: &MessageInfo, escrow: &Escrow) -> Result<(), ContractError>  {
+#[allow(clippy::too_many_arguments)]
+fn execute_approve(
+    deps: DepsMut,
+    env:Env,
+    info: MessageInfo,
+    collection: String,
+    token_id: String,
+    deliver_to: Option<String>,
+    deliver_msg: Option<Binary>
+)-> Result<Response, ContractError> {
+    
+    //validation check
+    deps.api.addr_validate(&collection)?;
 
-    let sent_denom = info.funds[0].denom.clone();
-    let sent_amount = info.funds[0].amount;
+    let sender = info.sender.to_string();
     
-    if info.funds.len() != 1 {
-        return Err(ContractError::OnlyOneCoinAvailable{});
-    } else{
-        if sent_denom != state.denom {
-            return Err(ContractError::NotExpectedDenom { denom: sent_denom });
-        } else{
-            if sent_amount != escrow.price{
-                return Err(ContractError::NotEnoughFunds {  })
-            }
-            else{
-                Ok(())
-            }
+    //load escrow
+    let mut escrow = load_escrow(deps.storage, &collection, &token_id)?;
+
+    let surplus = approve_check(&env.block, &escrow, &sender, &info.funds)?;
+    //settle at what the buyer actually paid
+    escrow.price = escrow.current_price(&env.block);
+
+    //an authorized payer only pays, the recipient decided where the NFT goes
+    let (deliver_to, deliver_msg) = if !escrow.recipient.is_empty() && escrow.recipient != sender {
+        if deliver_to.is_some() || deliver_msg.is_some() {
+            return Err(ContractError::Unauthorized {  })
         }
-    }
-    
-}
+        (escrow.payer_deliver_to.clone(), escrow.payer_deliver_msg.clone())
+    } else {
+        (deliver_to, deliver_msg)
+    };
+    //the NFT goes to the recipient unless the buyer names another owner,
+    //open auctions deliver to the buyer
+    let deliver_to = match deliver_to {
+        Some(deliver_to) => deps.api.addr_validate(&deliver_to)?.to_string(),
+        None if escrow.recipient.is_empty() => sender.clone(),
+        None => escrow.recipient.clone()
+    };
 
-fn nonpayable(info: &MessageInfo) -> Result<(), ContractError> {
-    if info.funds.len() > 0 {
-        return Err(ContractError::NonPayable{} )
-    }
-    else{
-        Ok(())
+    //the funds wait in the contract until the condition holds,
+    //milestone escrows pay them out tranche by tranche
+    let locked = match &escrow.condition {
+        _ if matches!(escrow.kind, EscrowKind::Milestones { .. }) => true,
+        Some(condition) => !condition_met(deps.as_ref(), condition)?,
+        None => false
+    };
+    if locked {
+        escrow.payment = Some(LockedPayment { payer: sender.clone(), deliver_to, deliver_msg });
+        store_escrow(deps.storage, &escrow)?;
+        return Ok(with_refund(Response::new(), &sender, surplus)
+            .add_attribute("action", "approve")
+            .add_attribute("payer", sender)
+            .add_event(escrow_event("escrow_lock", &escrow)))
     }
+
+    let res = settle_escrow(deps.storage, escrow, deliver_to, deliver_msg)?;
+
+    Ok(with_refund(res, &sender, surplus)
+        .add_attribute("action", "approve")
+        .add_attribute("payer", sender))
 }
 
-fn only_owner(state: &State, info: &MessageInfo) -> Result<(), ContractError> {
-    if info.sender.to_string() != state.admin {
-        return Err(ContractError::Unauthorized {  } )
+fn execute_release(
+    deps: DepsMut,
+    env:Env,
+    info: MessageInfo,
+    collection: String,
+    token_id: String
+)-> Result<Response, ContractError> {
+    
+    //validation check
+    deps.api.addr_validate(&collection)?;
+    nonpayable(&info)?;
+
+    //load escrow
+    let escrow = load_escrow(deps.storage, &collection, &token_id)?;
+
+    //milestone escrows are released milestone by milestone
+    if matches!(escrow.kind, EscrowKind::Milestones { .. }) {
+        return Err(ContractError::WrongEscrowKind {  })
+    }
+    let payment = escrow.payment.clone().ok_or(ContractError::NotPaid {  })?;
+    //after expiry the deal is off and the payment goes back on withdraw
+    if escrow.is_expired(&env.block){
+        return Err(ContractError::EscrowExpired {  })
+    }
+    if let Some(condition) = &escrow.condition {
+        if !condition_met(deps.as_ref(), condition)? {
+            return Err(ContractError::ConditionNotMet {  })
+        }
+    }
+
+    let res = settle_escrow(deps.storage, escrow, payment.deliver_to, payment.deliver_msg)?;
+
+    Ok(res
+        .add_attribute("action", "release")
+        .add_attribute("payer", payment.payer))
+}
+
+/// Runs the condition's smart query
+fn condition_met(deps: Deps, condition: &Condition) -> StdResult<bool> {
+    let request: QueryRequest<Empty> = QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: condition.contract.clone(),
+        msg: condition.msg.clone(),
+    });
+    deps.querier.query(&request)
+}
+
+/// Hands what is left of a locked payment back to its payer
+fn payment_refund(escrow: &Escrow) -> Vec<BankMsg> {
+    let payment = match &escrow.payment {
+        Some(payment) => payment,
+        None => return vec![]
+    };
+    let mut amount = escrow.price.clone();
+    //released milestones already went to the source
+    if let EscrowKind::Milestones { milestones, .. } = &escrow.kind {
+        let released: Uint128 = milestones
+            .iter()
+            .filter(|milestone| milestone.status == MilestoneStatus::Released)
+            .map(|milestone| milestone.amount)
+            .sum();
+        amount[0].amount -= released;
+    }
+    if amount.iter().all(|coin| coin.amount.is_zero()) {
+        return vec![]
+    }
+    vec![refund_msg(payment.payer.clone(), amount)]
+}
+
+#[allow(clippy::too_many_arguments)]
+fn execute_submit_milestone(
+    deps: DepsMut,
+    env:Env,
+    info: MessageInfo,
+    collection: String,
+    token_id: String,
+    index: u32,
+    work: Binary
+)-> Result<Response, ContractError> {
+    
+    //validation check
+    deps.api.addr_validate(&collection)?;
+    nonpayable(&info)?;
+
+    //load escrow
+    let (mut escrow, _) = load_milestone_escrow(deps.storage, &env.block, &collection, &token_id)?;
+    if info.sender != escrow.source {
+        return Err(ContractError::Unauthorized {  })
+    }
+
+    //work may be handed in again until it is approved
+    let milestone = milestone_mut(&mut escrow, index)?;
+    if !matches!(milestone.status, MilestoneStatus::Pending | MilestoneStatus::Submitted { .. }) {
+        return Err(ContractError::MilestoneState { index, action: "submitted".to_string() })
+    }
+    milestone.status = MilestoneStatus::Submitted { work };
+    store_escrow(deps.storage, &escrow)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "submit_milestone")
+        .add_event(milestone_event(&escrow, index, "submitted")))
+}
+
+fn execute_approve_milestone(
+    deps: DepsMut,
+    env:Env,
+    info: MessageInfo,
+    collection: String,
+    token_id: String,
+    index: u32
+)-> Result<Response, ContractError> {
+    
+    //validation check
+    deps.api.addr_validate(&collection)?;
+    nonpayable(&info)?;
+
+    //load escrow
+    let (mut escrow, payment) = load_milestone_escrow(deps.storage, &env.block, &collection, &token_id)?;
+    if info.sender != escrow.recipient && info.sender != payment.payer {
+        return Err(ContractError::Unauthorized {  })
+    }
+
+    let milestone = milestone_mut(&mut escrow, index)?;
+    if !matches!(milestone.status, MilestoneStatus::Submitted { .. }) {
+        return Err(ContractError::MilestoneState { index, action: "approved".to_string() })
+    }
+
+    let res = release_milestone(deps.storage, escrow, index)?;
+    Ok(res.add_attribute("action", "approve_milestone"))
+}
+
+fn execute_dispute_milestone(
+    deps: DepsMut,
+    env:Env,
+    info: MessageInfo,
+    collection: String,
+    token_id: String,
+    index: u32
+)-> Result<Response, ContractError> {
+    
+    //validation check
+    deps.api.addr_validate(&collection)?;
+    nonpayable(&info)?;
+
+    //load escrow
+    let (mut escrow, payment) = load_milestone_escrow(deps.storage, &env.block, &collection, &token_id)?;
+    if info.sender != escrow.source && info.sender != escrow.recipient && info.sender != payment.payer {
+        return Err(ContractError::Unauthorized {  })
+    }
+
+    let milestone = milestone_mut(&mut escrow, index)?;
+    if !matches!(milestone.status, MilestoneStatus::Pending | MilestoneStatus::Submitted { .. }) {
+        return Err(ContractError::MilestoneState { index, action: "disputed".to_string() })
+    }
+    milestone.status = MilestoneStatus::Disputed;
+    store_escrow(deps.storage, &escrow)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "dispute_milestone")
+        .add_event(milestone_event(&escrow, index, "disputed")))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn execute_resolve_dispute(
+    deps: DepsMut,
+    env:Env,
+    info: MessageInfo,
+    collection: String,
+    token_id: String,
+    index: u32,
+    release: bool
+)-> Result<Response, ContractError> {
+    
+    //validation check
+    deps.api.addr_validate(&collection)?;
+    nonpayable(&info)?;
+
+    //load escrow
+    let (mut escrow, _) = load_milestone_escrow(deps.storage, &env.block, &collection, &token_id)?;
+    match &escrow.kind {
+        EscrowKind::Milestones { arbiter, .. } if info.sender == *arbiter => {},
+        _ => return Err(ContractError::Unauthorized {  })
+    }
+
+    let milestone = milestone_mut(&mut escrow, index)?;
+    if milestone.status != MilestoneStatus::Disputed {
+        return Err(ContractError::MilestoneState { index, action: "resolved".to_string() })
+    }
+
+    if release {
+        let res = release_milestone(deps.storage, escrow, index)?;
+        return Ok(res.add_attribute("action", "resolve_dispute"))
+    }
+
+    //the payer wins: the deal ends with the rest of the payment refunded
+    remove_escrow(deps.storage, &escrow)?;
+
+    let cw721_transfer_msg = nft_transfer_msg(&collection, &escrow.source, &token_id, None)?;
+    let hooks = prepare_escrow_hooks(deps.storage, EscrowHookMsg::EscrowCancelled { escrow: escrow.clone() })?;
+
+    Ok(Response::new()
+        .add_attribute("action", "resolve_dispute")
+        .add_event(milestone_event(&escrow, index, "refunded"))
+        .add_message(cw721_transfer_msg)
+        .add_messages(payment_refund(&escrow))
+        .add_submessages(hooks)
+      )
+}
+
+/// Loads a paid, unexpired milestone escrow together with its payment
+fn load_milestone_escrow(
+    store: &dyn Storage,
+    block: &BlockInfo,
+    collection: &str,
+    token_id: &str
+) -> Result<(Escrow, LockedPayment), ContractError> {
+    let escrow = load_escrow(store, collection, token_id)?;
+    if !matches!(escrow.kind, EscrowKind::Milestones { .. }) {
+        return Err(ContractError::WrongEscrowKind {  })
+    }
+    let payment = escrow.payment.clone().ok_or(ContractError::NotPaid {  })?;
+    if escrow.is_expired(block){
+        return Err(ContractError::EscrowExpired {  })
+    }
+    Ok((escrow, payment))
+}
+
+fn milestone_mut(escrow: &mut Escrow, index: u32) -> Result<&mut Milestone, ContractError> {
+    match &mut escrow.kind {
+        EscrowKind::Milestones { milestones, .. } => milestones
+            .get_mut(index as usize)
+            .ok_or(ContractError::NoMilestone { index }),
+        _ => Err(ContractError::WrongEscrowKind {  })
+    }
+}
+
+/// Pays a milestone out to the source, the last one also delivers the NFT
+fn release_milestone(store: &mut dyn Storage, mut escrow: Escrow, index: u32) -> Result<Response, ContractError> {
+    let denom = escrow.price[0].denom.clone();
+    let milestone = milestone_mut(&mut escrow, index)?;
+    milestone.status = MilestoneStatus::Released;
+    let tranche = Coin { denom, amount: milestone.amount };
+
+    let payments = payout_msgs(&escrow, std::slice::from_ref(&tranche));
+    let event = milestone_event(&escrow, index, "released")
+        .add_attribute("amount", tranche.to_string());
+
+    let done = match &escrow.kind {
+        EscrowKind::Milestones { milestones, .. } => milestones.iter().all(|milestone| milestone.status == MilestoneStatus::Released),
+        _ => false
+    };
+    if !done {
+        store_escrow(store, &escrow)?;
+        return Ok(Response::new()
+            .add_messages(payments)
+            .add_event(event))
+    }
+
+    let payment = escrow.payment.clone().ok_or(ContractError::NotPaid {  })?;
+    let res = settle_escrow(store, escrow, payment.deliver_to, payment.deliver_msg)?;
+    Ok(res
+        .add_messages(payments)
+        .add_event(event))
+}
+
+fn milestone_event(escrow: &Escrow, index: u32, status: &str) -> Event {
+    escrow_event("escrow_milestone", escrow)
+        .add_attribute("milestone", index.to_string())
+        .add_attribute("status", status)
+}
+
+fn execute_claim_htlc(
+    deps: DepsMut,
+    env:Env,
+    info: MessageInfo,
+    collection: String,
+    token_id: String,
+    preimage: Binary
+)-> Result<Response, ContractError> {
+    
+    //validation check
+    deps.api.addr_validate(&collection)?;
+
+    //load escrow
+    let escrow = load_escrow(deps.storage, &collection, &token_id)?;
+
+    let hash_lock = match &escrow.kind {
+        EscrowKind::Htlc { hash_lock } => hash_lock.clone(),
+        _ => return Err(ContractError::WrongEscrowKind {  })
+    };
+    if escrow.is_expired(&env.block){
+        return Err(ContractError::EscrowExpired {  })
+    }
+    if Sha256::digest(preimage.as_slice()).as_slice() != hash_lock.as_slice() {
+        return Err(ContractError::InvalidPreimage {  })
+    }
+    let surplus = fund_check(&info.funds, &escrow.price)?;
+
+    //keep the secret around for the counterparty's leg on the other chain
+    REVEALED_PREIMAGES.save(deps.storage, hash_lock.as_slice(), &preimage)?;
+
+    let event = escrow_event("escrow_claim", &escrow)
+        .add_attribute("preimage", preimage.to_base64());
+    let deliver_to = escrow.recipient.clone();
+    let res = settle_escrow(deps.storage, escrow, deliver_to, None)?;
+
+    Ok(with_refund(res, info.sender.as_str(), surplus)
+        .add_attribute("action", "claim_htlc")
+        .add_event(event))
+}
+
+fn execute_bid(
+    deps: DepsMut,
+    env:Env,
+    info: MessageInfo,
+    collection: String,
+    token_id: String
+)-> Result<Response, ContractError> {
+    
+    //validation check
+    deps.api.addr_validate(&collection)?;
+
+    //load escrow
+    let mut escrow = load_escrow(deps.storage, &collection, &token_id)?;
+
+    let (min_increment, extension) = match escrow.kind {
+        EscrowKind::EnglishAuction { min_increment, extension, .. } => (min_increment, extension),
+        _ => return Err(ContractError::WrongEscrowKind {  })
+    };
+    if escrow.is_expired(&env.block){
+        return Err(ContractError::EscrowExpired {  })
+    }
+    if info.sender == escrow.source {
+        return Err(ContractError::Unauthorized {  } )
+    }
+
+    //the first bid has to meet the opening price, every other one has to
+    //beat the leading bid by the increment
+    let leading = leading_bid(deps.storage, escrow.id)?;
+    let minimum = match &leading {
+        Some(bid) => Coin { denom: bid.amount.denom.clone(), amount: bid.amount.amount + min_increment },
+        None => escrow.price[0].clone()
+    };
+    let surplus = fund_check(&info.funds, std::slice::from_ref(&minimum))?;
+    let amount = Coin {
+        amount: minimum.amount + surplus.iter().map(|coin| coin.amount).sum::<Uint128>(),
+        denom: minimum.denom
+    };
+
+    let bid = Bid {
+        id: leading.as_ref().map_or(1, |bid| bid.id + 1),
+        bidder: info.sender.to_string(),
+        amount,
+        placed_at: env.block.time,
+    };
+    BIDS.save(deps.storage, (escrow.id, bid.id), &bid)?;
+
+    //a late bid gives the others time to answer
+    let extended = env.block.time.plus_seconds(extension);
+    if escrow.expires_at < extended {
+        escrow.expires_at = extended;
+        store_escrow(deps.storage, &escrow)?;
+    }
+
+    let mut res = Response::new()
+        .add_attribute("action", "bid")
+        .add_event(escrow_event("escrow_bid", &escrow)
+            .add_attribute("bidder", bid.bidder)
+            .add_attribute("amount", bid.amount.to_string()));
+
+    //the outbid bidder gets its funds back right away
+    if let Some(outbid) = leading {
+        res = res.add_message(BankMsg::Send { 
+            to_address: outbid.bidder, 
+            amount: vec![outbid.amount]
+        });
+    }
+    Ok(res)
+}
+
+fn execute_commit_bid(
+    deps: DepsMut,
+    env:Env,
+    info: MessageInfo,
+    collection: String,
+    token_id: String,
+    commitment: Binary
+)-> Result<Response, ContractError> {
+    
+    //validation check
+    deps.api.addr_validate(&collection)?;
+
+    //load escrow
+    let escrow = load_escrow(deps.storage, &collection, &token_id)?;
+
+    let commit_end = match escrow.kind {
+        EscrowKind::SealedBidAuction { commit_end, .. } => commit_end,
+        _ => return Err(ContractError::WrongEscrowKind {  })
+    };
+    if env.block.time >= commit_end {
+        return Err(ContractError::AuctionPhase { phase: "commit".to_string() })
+    }
+    if info.sender == escrow.source {
+        return Err(ContractError::Unauthorized {  } )
+    }
+    if commitment.len() != 32 {
+        return Err(ContractError::InvalidHashLock {  })
+    }
+    if SEALED_BIDS.has(deps.storage, (escrow.id, &info.sender)) {
+        return Err(ContractError::AlreadyCommitted {  })
+    }
+
+    //the deposit can be anything above the minimum bid, hiding the bid itself
+    let minimum = &escrow.price[0];
+    let surplus = fund_check(&info.funds, std::slice::from_ref(minimum))?;
+    let deposit = Coin {
+        amount: minimum.amount + surplus.iter().map(|coin| coin.amount).sum::<Uint128>(),
+        denom: minimum.denom.clone()
+    };
+
+    SEALED_BIDS.save(deps.storage, (escrow.id, &info.sender), &SealedBid {
+        bidder: info.sender.to_string(),
+        commitment,
+        deposit,
+        revealed: None,
+    })?;
+
+    Ok(Response::new()
+        .add_attribute("action", "commit_bid")
+        .add_event(escrow_event("escrow_commit_bid", &escrow)
+            .add_attribute("bidder", info.sender)))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn execute_reveal_bid(
+    deps: DepsMut,
+    env:Env,
+    info: MessageInfo,
+    collection: String,
+    token_id: String,
+    amount: Uint128,
+    salt: Binary
+)-> Result<Response, ContractError> {
+    
+    //validation check
+    deps.api.addr_validate(&collection)?;
+    nonpayable(&info)?;
+
+    //load escrow
+    let escrow = load_escrow(deps.storage, &collection, &token_id)?;
+
+    let commit_end = match escrow.kind {
+        EscrowKind::SealedBidAuction { commit_end, .. } => commit_end,
+        _ => return Err(ContractError::WrongEscrowKind {  })
+    };
+    if env.block.time < commit_end || escrow.is_expired(&env.block) {
+        return Err(ContractError::AuctionPhase { phase: "reveal".to_string() })
+    }
+
+    let mut bid = SEALED_BIDS
+        .may_load(deps.storage, (escrow.id, &info.sender))?
+        .ok_or(ContractError::Unauthorized {  })?;
+    if bid.revealed.is_some()
+        || bid.commitment != sealed_bid_commitment(info.sender.as_str(), amount, &salt)
+        || amount > bid.deposit.amount {
+        return Err(ContractError::InvalidReveal {  })
+    }
+    let minimum = &escrow.price[0];
+    if amount < minimum.amount {
+        return Err(ContractError::Underpayment {
+            denom: minimum.denom.clone(),
+            expected: minimum.amount,
+            received: amount
+        })
+    }
+    bid.revealed = Some(amount);
+    SEALED_BIDS.save(deps.storage, (escrow.id, &info.sender), &bid)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "reveal_bid")
+        .add_event(escrow_event("escrow_reveal_bid", &escrow)
+            .add_attribute("bidder", info.sender)
+            .add_attribute("amount", amount.to_string())))
+}
+
+fn execute_settle(
+    deps: DepsMut,
+    env:Env,
+    info: MessageInfo,
+    collection: String,
+    token_id: String
+)-> Result<Response, ContractError> {
+    
+    //validation check
+    deps.api.addr_validate(&collection)?;
+    nonpayable(&info)?;
+
+    //load escrow
+    let mut escrow = load_escrow(deps.storage, &collection, &token_id)?;
+
+    if !matches!(escrow.kind, EscrowKind::EnglishAuction { .. } | EscrowKind::SealedBidAuction { .. }) {
+        return Err(ContractError::WrongEscrowKind {  })
+    }
+    if !escrow.is_expired(&env.block){
+        return Err(ContractError::EscrowNotExpired {  })
+    }
+
+    //the winner and what it pays, plus the funds to hand back
+    let (sale, refunds) = match escrow.kind.clone() {
+        EscrowKind::EnglishAuction { reserve, .. } => match leading_bid(deps.storage, escrow.id)? {
+            Some(bid) if bid.amount.amount >= reserve => (Some((bid.bidder, bid.amount)), vec![]),
+            Some(bid) => (None, vec![refund_msg(bid.bidder, vec![bid.amount])]),
+            None => (None, vec![])
+        },
+        EscrowKind::SealedBidAuction { pricing, .. } => sealed_bid_outcome(deps.storage, &escrow, pricing)?,
+        _ => unreachable!()
+    };
+
+    match sale {
+        Some((winner, price)) => {
+            escrow.price = vec![price];
+            let res = settle_escrow(deps.storage, escrow, winner.clone(), None)?;
+            Ok(res
+                .add_messages(refunds)
+                .add_attribute("action", "settle")
+                .add_attribute("winner", winner))
+        },
+        None => {
+            //unsold: the NFT goes back to its source
+            remove_escrow(deps.storage, &escrow)?;
+
+            let cw721_transfer_msg = nft_transfer_msg(&collection, &escrow.source, &token_id, None)?;
+            let hooks = prepare_escrow_hooks(deps.storage, EscrowHookMsg::EscrowWithdrawn { escrow: escrow.clone() })?;
+
+            Ok(Response::new()
+                .add_attribute("action", "settle")
+                .add_event(escrow_event("escrow_unsold", &escrow))
+                .add_message(cw721_transfer_msg)
+                .add_messages(refunds)
+                .add_submessages(hooks))
+        }
+    }
+}
+
+/// Picks the highest revealed bid, ties going to the lower bidder address,
+/// and refunds every deposit beyond what the winner pays
+#[allow(clippy::type_complexity)]
+fn sealed_bid_outcome(
+    store: &mut dyn Storage,
+    escrow: &Escrow,
+    pricing: SealedBidPricing
+) -> StdResult<(Option<(String, Coin)>, Vec<BankMsg>)> {
+    let bids = SEALED_BIDS
+        .prefix(escrow.id)
+        .range(store, None, None, cosmwasm_std::Order::Ascending)
+        .map(|res| res.map(|(_, bid)| bid))
+        .collect::<StdResult<Vec<_>>>()?;
+    for bid in &bids {
+        SEALED_BIDS.remove(store, (escrow.id, &Addr::unchecked(&bid.bidder)));
+    }
+
+    let mut revealed: Vec<&SealedBid> = bids.iter().filter(|bid| bid.revealed.is_some()).collect();
+    revealed.sort_by_key(|bid| std::cmp::Reverse(bid.revealed));
+
+    let sale = revealed.first().map(|winner| {
+        let amount = match pricing {
+            SealedBidPricing::FirstPrice => winner.revealed.unwrap_or_default(),
+            SealedBidPricing::SecondPrice => revealed
+                .get(1)
+                .and_then(|second| second.revealed)
+                .unwrap_or(escrow.price[0].amount),
+        };
+        (winner.bidder.clone(), Coin { denom: winner.deposit.denom.clone(), amount })
+    });
+
+    let refunds = bids
+        .into_iter()
+        .filter_map(|bid| {
+            let mut deposit = bid.deposit;
+            if let Some((winner, price)) = &sale {
+                if &bid.bidder == winner {
+                    deposit.amount -= price.amount;
+                }
+            }
+            (!deposit.amount.is_zero()).then(|| refund_msg(bid.bidder, vec![deposit]))
+        })
+        .collect();
+
+    Ok((sale, refunds))
+}
+
+fn refund_msg(to_address: String, amount: Vec<Coin>) -> BankMsg {
+    BankMsg::Send { to_address, amount }
+}
+
+/// The leading bid of an English auction, if any
+fn leading_bid(store: &dyn Storage, escrow_id: u64) -> StdResult<Option<Bid>> {
+    BIDS.prefix(escrow_id)
+        .range(store, None, None, cosmwasm_std::Order::Descending)
+        .next()
+        .map(|res| res.map(|(_, bid)| bid))
+        .transpose()
+}
+
+fn execute_register_pubkey(
+    deps: DepsMut,
+    info: MessageInfo,
+    pubkey: Binary
+)-> Result<Response, ContractError> {
+    nonpayable(&info)?;
+
+    PUBKEYS.save(deps.storage, &info.sender, &pubkey)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "register_pubkey")
+        .add_attribute("sender", info.sender)
+      )
+}
+
+fn execute_fulfill_signed_offer(
+    deps: DepsMut,
+    env:Env,
+    info: MessageInfo,
+    offer: SignedOffer,
+    signature: Binary
+)-> Result<Response, ContractError> {
+
+    //validation check
+    deps.api.addr_validate(&offer.collection)?;
+    deps.api.addr_validate(&offer.recipient)?;
+    let source = deps.api.addr_validate(&offer.source)?;
+    validate_price(&offer.price)?;
+
+    if offer.contract != env.contract.address || offer.chain_id != env.block.chain_id {
+        return Err(ContractError::WrongOfferDomain { contract: offer.contract, chain_id: offer.chain_id })
+    }
+
+    let pubkey = PUBKEYS
+        .may_load(deps.storage, &source)?
+        .ok_or(ContractError::NoPubkey { address: offer.source.clone() })?;
+    if USED_NONCES.has(deps.storage, (&offer.source, offer.nonce)) {
+        return Err(ContractError::NonceUsed { nonce: offer.nonce })
+    }
+    let hash = Sha256::digest(&to_vec(&offer)?);
+    if !deps.api.secp256k1_verify(&hash, &signature, &pubkey).unwrap_or(false) {
+        return Err(ContractError::InvalidSignature {  })
+    }
+
+    //the NFT is still with its source, so it can not be escrowed here as well
+    if escrows().may_load(deps.storage, escrow_key(&offer.collection, &offer.token_id))?.is_some() {
+        return Err(ContractError::EscrowExists {  })
+    }
+    //a signed offer outlives transfers, only the current owner's still counts
+    let owner: OwnerOfResponse = deps.querier.query_wasm_smart(
+        offer.collection.clone(),
+        &Cw721QueryMsg::OwnerOf { token_id: offer.token_id.clone(), include_expired: None }
+    )?;
+    if owner.owner != offer.source {
+        return Err(ContractError::NotTokenOwner { address: offer.source })
+    }
+
+    let escrow = Escrow {
+        id: next_escrow_id(deps.storage)?,
+        source: offer.source,
+        recipient: offer.recipient,
+        price: offer.price,
+        expires_at: offer.expires_at,
+        collection: offer.collection,
+        token_id: offer.token_id,
+        payer: None,
+        payer_deliver_to: None,
+        payer_deliver_msg: None,
+        payees: vec![],
+        kind: EscrowKind::Standard,
+        condition: None,
+        payment: None,
+    };
+
+    let sender = info.sender.to_string();
+    let surplus = approve_check(&env.block, &escrow, &sender, &info.funds)?;
+
+    USED_NONCES.save(deps.storage, (&escrow.source, offer.nonce), &())?;
+
+    //goes through the regular bookkeeping, the transfer out of the source
+    //relies on the cw721 approval it gave this contract
+    store_escrow(deps.storage, &escrow)?;
+    let deliver_to = escrow.recipient.clone();
+    let res = settle_escrow(deps.storage, escrow, deliver_to, None)?;
+
+    Ok(with_refund(res, &sender, surplus)
+        .add_attribute("action", "fulfill_signed_offer")
+        .add_attribute("nonce", offer.nonce.to_string()))
+}
+
+fn execute_make_offer(
+    deps: DepsMut,
+    env:Env,
+    info: MessageInfo,
+    collection: String,
+    token_id: String,
+    expiration: Timestamp
+)-> Result<Response, ContractError> {
+
+    //validation check
+    deps.api.addr_validate(&collection)?;
+    validate_price(&info.funds)?;
+    if expiration <= env.block.time {
+        return Err(ContractError::OfferExpired {  })
+    }
+
+    let offer = Offer {
+        id: next_offer_id(deps.storage)?,
+        bidder: info.sender.to_string(),
+        collection,
+        token_id,
+        price: info.funds,
+        expires_at: expiration,
+    };
+    offers().save(deps.storage, offer.id, &offer)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "make_offer")
+        .add_event(offer_event("offer_create", &offer)))
+}
+
+fn execute_withdraw_offer(
+    deps: DepsMut,
+    env:Env,
+    info: MessageInfo,
+    offer_id: u64
+)-> Result<Response, ContractError> {
+
+    //validation check
+    nonpayable(&info)?;
+
+    let offer = offers()
+        .may_load(deps.storage, offer_id)?
+        .ok_or(ContractError::NoOffer {  })?;
+    if info.sender != offer.bidder {
+        return Err(ContractError::Unauthorized {  })
+    }
+    //the funds stay committed until the offer runs out
+    if !offer.is_expired(&env.block) {
+        return Err(ContractError::OfferNotExpired {  })
+    }
+    offers().remove(deps.storage, offer_id)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "withdraw_offer")
+        .add_event(offer_event("offer_withdraw", &offer))
+        .add_message(refund_msg(offer.bidder.clone(), offer.price.clone())))
+}
+
+/// The NFT's owner sent it in to sell it to the offer's bidder
+fn execute_accept_offer(
+    deps: DepsMut,
+    env:Env,
+    info: MessageInfo,
+    rcv_msg: Cw721ReceiveMsg,
+    offer_id: u64
+)-> Result<Response, ContractError> {
+
+    let offer = offers()
+        .may_load(deps.storage, offer_id)?
+        .ok_or(ContractError::NoOffer {  })?;
+    if info.sender != offer.collection || rcv_msg.token_id != offer.token_id {
+        return Err(ContractError::OfferMismatch {  })
+    }
+    if offer.is_expired(&env.block) {
+        return Err(ContractError::OfferExpired {  })
+    }
+    offers().remove(deps.storage, offer_id)?;
+
+    let escrow = Escrow {
+        id: next_escrow_id(deps.storage)?,
+        source: rcv_msg.sender,
+        recipient: offer.bidder.clone(),
+        price: offer.price.clone(),
+        expires_at: offer.expires_at,
+        collection: offer.collection.clone(),
+        token_id: offer.token_id.clone(),
+        payer: None,
+        payer_deliver_to: None,
+        payer_deliver_msg: None,
+        payees: vec![],
+        kind: EscrowKind::Standard,
+        condition: None,
+        payment: None,
+    };
+
+    //goes through the regular bookkeeping, paid with the offer's funds
+    store_escrow(deps.storage, &escrow)?;
+    let deliver_to = escrow.recipient.clone();
+    let res = settle_escrow(deps.storage, escrow, deliver_to, None)?;
+
+    Ok(res
+        .add_attribute("action", "accept_offer")
+        .add_event(offer_event("offer_accept", &offer)))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn execute_make_collection_offer(
+    deps: DepsMut,
+    env:Env,
+    info: MessageInfo,
+    collection: String,
+    price: Coin,
+    quantity: Option<u32>,
+    expiration: Timestamp
+)-> Result<Response, ContractError> {
+
+    //validation check
+    deps.api.addr_validate(&collection)?;
+    validate_price(std::slice::from_ref(&price))?;
+    let quantity = quantity.unwrap_or(1);
+    if quantity == 0 {
+        return Err(ContractError::InvalidQuantity {  })
+    }
+    if expiration <= env.block.time {
+        return Err(ContractError::OfferExpired {  })
+    }
+    let total = Coin {
+        denom: price.denom.clone(),
+        amount: price.amount.checked_mul(Uint128::from(quantity)).map_err(StdError::from)?
+    };
+    let surplus = fund_check(&info.funds, &[total])?;
+
+    let offer = CollectionOffer {
+        id: next_offer_id(deps.storage)?,
+        bidder: info.sender.to_string(),
+        collection,
+        price,
+        quantity,
+        expires_at: expiration,
+    };
+    collection_offers().save(deps.storage, offer.id, &offer)?;
+
+    Ok(with_refund(Response::new(), info.sender.as_str(), surplus)
+        .add_attribute("action", "make_collection_offer")
+        .add_event(collection_offer_event("collection_offer_create", &offer)))
+}
+
+fn execute_withdraw_collection_offer(
+    deps: DepsMut,
+    env:Env,
+    info: MessageInfo,
+    offer_id: u64
+)-> Result<Response, ContractError> {
+
+    //validation check
+    nonpayable(&info)?;
+
+    let offer = collection_offers()
+        .may_load(deps.storage, offer_id)?
+        .ok_or(ContractError::NoOffer {  })?;
+    if info.sender != offer.bidder {
+        return Err(ContractError::Unauthorized {  })
+    }
+    //the funds stay committed until the offer runs out
+    if !offer.is_expired(&env.block) {
+        return Err(ContractError::OfferNotExpired {  })
+    }
+    collection_offers().remove(deps.storage, offer_id)?;
+
+    let unfilled = Coin {
+        denom: offer.price.denom.clone(),
+        amount: offer.price.amount * Uint128::from(offer.quantity)
+    };
+
+    Ok(Response::new()
+        .add_attribute("action", "withdraw_collection_offer")
+        .add_event(collection_offer_event("collection_offer_withdraw", &offer))
+        .add_message(refund_msg(offer.bidder.clone(), vec![unfilled])))
+}
+
+/// A holder sent in an NFT of the offer's collection to sell one unit
+fn execute_fill_collection_offer(
+    deps: DepsMut,
+    env:Env,
+    info: MessageInfo,
+    rcv_msg: Cw721ReceiveMsg,
+    offer_id: u64
+)-> Result<Response, ContractError> {
+
+    let mut offer = collection_offers()
+        .may_load(deps.storage, offer_id)?
+        .ok_or(ContractError::NoOffer {  })?;
+    if info.sender != offer.collection {
+        return Err(ContractError::OfferMismatch {  })
+    }
+    if offer.is_expired(&env.block) {
+        return Err(ContractError::OfferExpired {  })
+    }
+
+    //the last unit closes the offer
+    offer.quantity -= 1;
+    if offer.quantity == 0 {
+        collection_offers().remove(deps.storage, offer_id)?;
+    } else {
+        collection_offers().save(deps.storage, offer_id, &offer)?;
+    }
+
+    let escrow = Escrow {
+        id: next_escrow_id(deps.storage)?,
+        source: rcv_msg.sender,
+        recipient: offer.bidder.clone(),
+        price: vec![offer.price.clone()],
+        expires_at: offer.expires_at,
+        collection: offer.collection.clone(),
+        token_id: rcv_msg.token_id,
+        payer: None,
+        payer_deliver_to: None,
+        payer_deliver_msg: None,
+        payees: vec![],
+        kind: EscrowKind::Standard,
+        condition: None,
+        payment: None,
+    };
+
+    //goes through the regular bookkeeping, paid with the offer's funds
+    store_escrow(deps.storage, &escrow)?;
+    let event = collection_offer_event("collection_offer_fill", &offer)
+        .add_attribute("token_id", escrow.token_id.clone());
+    let deliver_to = escrow.recipient.clone();
+    let res = settle_escrow(deps.storage, escrow, deliver_to, None)?;
+
+    Ok(res
+        .add_attribute("action", "fill_collection_offer")
+        .add_event(event))
+}
+
+fn collection_offer_event(ty: &str, offer: &CollectionOffer) -> Event {
+    Event::new(ty)
+        .add_attribute("offer_id", offer.id.to_string())
+        .add_attribute("bidder", offer.bidder.clone())
+        .add_attribute("collection", offer.collection.clone())
+        .add_attribute("price", offer.price.to_string())
+        .add_attribute("quantity", offer.quantity.to_string())
+        .add_attribute("expires_at", offer.expires_at.seconds().to_string())
+}
+
+fn offer_event(ty: &str, offer: &Offer) -> Event {
+    let price: Vec<String> = offer.price.iter().map(|coin| coin.to_string()).collect();
+    Event::new(ty)
+        .add_attribute("offer_id", offer.id.to_string())
+        .add_attribute("bidder", offer.bidder.clone())
+        .add_attribute("collection", offer.collection.clone())
+        .add_attribute("token_id", offer.token_id.clone())
+        .add_attribute("price", price.join(","))
+        .add_attribute("expires_at", offer.expires_at.seconds().to_string())
+}
+
+fn next_offer_id(store: &mut dyn Storage) -> StdResult<u64> {
+    let id = OFFER_SEQ.may_load(store)?.unwrap_or_default() + 1;
+    OFFER_SEQ.save(store, &id)?;
+    Ok(id)
+}
+
+/// Sends whatever was paid above the price straight back to the payer
+fn with_refund(res: Response, payer: &str, surplus: Vec<Coin>) -> Response {
+    if surplus.is_empty() {
+        return res
+    }
+    res.add_message(BankMsg::Send { 
+        to_address: payer.to_string(), 
+        amount: surplus
+    })
+}
+
+/// Pays the source the whole price basket and hands the NFT to the buyer. The NFT transfer is a
+/// sub-message; the settlement is only finalized once `reply` has confirmed
+/// the buyer really owns the token.
+fn settle_escrow(
+    store: &mut dyn Storage,
+    escrow: Escrow,
+    buyer: String,
+    deliver_msg: Option<Binary>
+) -> Result<Response, ContractError> {
+    //remove current escrow 
+    remove_escrow(store, &escrow)?;
+
+    //milestone escrows have already paid out every tranche
+    let payments = match escrow.kind {
+        EscrowKind::Milestones { .. } => vec![],
+        _ => payout_msgs(&escrow, &escrow.price)
+    };
+
+    let sent = deliver_msg.is_some();
+    let cw721_transfer_msg = nft_transfer_msg(&escrow.collection, &buyer, &escrow.token_id, deliver_msg)?;
+
+    let reply_id = settle_reply_id(escrow.id);
+    PENDING_SETTLEMENTS.save(store, escrow.id, &Settlement { escrow, buyer, sent })?;
+
+    Ok(Response::new()
+        .add_messages(payments)
+        .add_submessage(SubMsg::reply_on_success(cw721_transfer_msg, reply_id)))
+}
+
+/// Pays the proceeds to the source, or splits every coin among the payees
+/// with the rounding dust going to the first payee
+fn payout_msgs(escrow: &Escrow, proceeds: &[Coin]) -> Vec<CosmosMsg> {
+    if proceeds.is_empty() {
+        return vec![]
+    }
+    if escrow.payees.is_empty() {
+        return vec![CosmosMsg::Bank(BankMsg::Send { 
+            to_address: escrow.source.clone(), 
+            amount: proceeds.to_vec()
+        })]
+    }
+
+    let mut payouts: Vec<Vec<Coin>> = vec![vec![]; escrow.payees.len()];
+    for coin in proceeds {
+        let mut amounts: Vec<Uint128> = escrow.payees
+            .iter()
+            .map(|payee| coin.amount.multiply_ratio(payee.share_bps, TOTAL_SHARE_BPS))
+            .collect();
+        let paid: Uint128 = amounts.iter().copied().sum();
+        amounts[0] += coin.amount - paid;
+
+        for (payout, amount) in payouts.iter_mut().zip(amounts) {
+            if !amount.is_zero() {
+                payout.push(Coin { denom: coin.denom.clone(), amount });
+            }
+        }
+    }
+
+    escrow.payees
+        .iter()
+        .zip(payouts)
+        .filter(|(_, amount)| !amount.is_empty())
+        .map(|(payee, amount)| CosmosMsg::Bank(BankMsg::Send { 
+            to_address: payee.address.clone(), 
+            amount
+        }))
+        .collect()
+}
+
+/// Checks the terms of a new escrow against its kind. Every kind needs a
+/// valid price except HTLCs, which only need a SHA-256 hash lock. Auctions
+/// and milestone escrows take a single-coin price; English and sealed-bid
+/// auctions leave the recipient empty and need a positive increment or a
+/// commit phase ending before expiry; Dutch auctions fall to a lower end
+/// price from a start before expiry, in non-zero steps; milestones start
+/// pending, are worth more than zero and add up to the price.
+fn validate_terms(recipient: &str, price: &[Coin], kind: &EscrowKind, expiration: Timestamp) -> Result<(), ContractError> {
+    let invalid_auction = |reason: &str| Err(ContractError::InvalidAuction { reason: reason.to_string() });
+    match kind {
+        EscrowKind::Standard => validate_price(price),
+        EscrowKind::EnglishAuction { min_increment, .. } => {
+            validate_price(price)?;
+            //bids are open to anyone, the winner takes the NFT
+            if !recipient.is_empty() {
+                return invalid_auction("the recipient must be left empty")
+            }
+            if price.len() != 1 {
+                return invalid_auction("the opening price must be a single coin")
+            }
+            if min_increment.is_zero() {
+                return invalid_auction("the minimum increment must be greater than zero")
+            }
+            Ok(())
+        },
+        EscrowKind::SealedBidAuction { commit_end, .. } => {
+            validate_price(price)?;
+            if !recipient.is_empty() {
+                return invalid_auction("the recipient must be left empty")
+            }
+            if price.len() != 1 {
+                return invalid_auction("the minimum bid must be a single coin")
+            }
+            if *commit_end >= expiration {
+                return invalid_auction("the commit phase must end before the auction expires")
+            }
+            Ok(())
+        },
+        EscrowKind::DutchAuction { end_price, start_time, curve } => {
+            validate_price(price)?;
+            if price.len() != 1 {
+                return invalid_auction("the start price must be a single coin")
+            }
+            if *end_price >= price[0].amount {
+                return invalid_auction("the end price must be below the start price")
+            }
+            if *start_time >= expiration {
+                return invalid_auction("the auction must start before it expires")
+            }
+            if *curve == (DecayCurve::Stepped { interval: 0 }) {
+                return invalid_auction("the step interval must be greater than zero")
+            }
+            Ok(())
+        },
+        EscrowKind::Milestones { milestones, .. } => {
+            let invalid_milestones = |reason: &str| Err(ContractError::InvalidMilestones { reason: reason.to_string() });
+            validate_price(price)?;
+            if price.len() != 1 {
+                return invalid_milestones("the price must be a single coin")
+            }
+            if milestones.is_empty() {
+                return invalid_milestones("there must be at least one milestone")
+            }
+            if milestones.iter().any(|milestone| milestone.amount.is_zero()) {
+                return invalid_milestones("every milestone must be worth more than zero")
+            }
+            if milestones.iter().any(|milestone| milestone.status != MilestoneStatus::Pending) {
+                return invalid_milestones("every milestone must start pending")
+            }
+            if milestones.iter().map(|milestone| milestone.amount).sum::<Uint128>() != price[0].amount {
+                return invalid_milestones("the milestone amounts must add up to the price")
+            }
+            Ok(())
+        },
+        EscrowKind::Htlc { hash_lock } => {
+            if hash_lock.len() != 32 {
+                return Err(ContractError::InvalidHashLock {  })
+            }
+            if price.is_empty() {
+                return Ok(())
+            }
+            validate_price(price)
+        }
+    }
+}
+
+/// A price needs at least one coin, none of them zero and no denom twice
+fn validate_price(price: &[Coin]) -> Result<(), ContractError> {
+    if price.is_empty() || price.iter().any(|coin| coin.amount.is_zero()) {
+        return Err(ContractError::NotEnoughFunds {  })
+    }
+    for (i, coin) in price.iter().enumerate() {
+        if price[..i].iter().any(|other| other.denom == coin.denom) {
+            return Err(ContractError::DuplicateDenom { denom: coin.denom.clone() })
+        }
+    }
+    Ok(())
+}
+
+fn validate_payees(api: &dyn Api, payees: &[Payee]) -> Result<(), ContractError> {
+    if payees.is_empty() {
+        return Ok(())
+    }
+    for payee in payees {
+        api.addr_validate(&payee.address)?;
+    }
+    let total: u64 = payees.iter().map(|payee| payee.share_bps as u64).sum();
+    if total != TOTAL_SHARE_BPS as u64 {
+        return Err(ContractError::InvalidPayeeShares { total })
+    }
+    Ok(())
+}
+
+/// Called from `reply` once the NFT transfer succeeded
+fn finalize_settlement(deps: DepsMut, env: Env, escrow_id: u64) -> Result<Response, ContractError> {
+    let Settlement { escrow, buyer, sent } = PENDING_SETTLEMENTS.load(deps.storage, escrow_id)?;
+
+    let owner: OwnerOfResponse = deps.querier.query_wasm_smart(
+        escrow.collection.clone(),
+        &Cw721QueryMsg::OwnerOf { token_id: escrow.token_id.clone(), include_expired: None }
+    )?;
+    //a `SendNft` receiver may already have passed the NFT on
+    let delivered = if sent {
+        owner.owner != env.contract.address
+    } else {
+        owner.owner == buyer
+    };
+    if !delivered {
+        return Err(ContractError::SettlementNotVerified { expected: buyer, owner: owner.owner })
+    }
+    PENDING_SETTLEMENTS.remove(deps.storage, escrow_id);
+
+    update_stats(deps.storage, &escrow.collection, |stats| stats.record_sale(&escrow.price))?;
+
+    let hooks = prepare_escrow_hooks(deps.storage, EscrowHookMsg::EscrowSettled { escrow: escrow.clone(), buyer: buyer.clone() })?;
+
+    //the recipient may be empty or differ from whoever ended up with the NFT
+    Ok(Response::new()
+        .add_event(escrow_event("escrow_approve", &escrow).add_attribute("buyer", buyer))
+        .add_submessages(hooks))
+}
+
+fn execute_change_config(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    state: State
+) -> Result<Response, ContractError> {
+    //only the current admin may change the config
+    let current = CONFIG.load(deps.storage)?;
+    only_owner(&current, info.sender.as_str())?;
+
+    CONFIG.save(deps.storage, &state)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "change_config")
+        .add_event(Event::new("escrow_change_config")
+            .add_attribute("admin", state.admin)
+            .add_attribute("denom", state.denom))
+      )
+
+}
+
+
+
+fn execute_add_hook(
+    deps: DepsMut,
+    info: MessageInfo,
+    addr: String
+) -> Result<Response, ContractError> {
+    let state = CONFIG.load(deps.storage)?;
+    only_owner(&state, info.sender.as_str())?;
+
+    let hook = deps.api.addr_validate(&addr)?;
+    HOOKS.add_hook(deps.storage, hook)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "add_hook")
+        .add_attribute("hook", addr)
+      )
+}
+
+fn execute_remove_hook(
+    deps: DepsMut,
+    info: MessageInfo,
+    addr: String
+) -> Result<Response, ContractError> {
+    let state = CONFIG.load(deps.storage)?;
+    only_owner(&state, info.sender.as_str())?;
+
+    let hook = deps.api.addr_validate(&addr)?;
+    HOOKS.remove_hook(deps.storage, hook)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "remove_hook")
+        .add_attribute("hook", addr)
+      )
+}
+
+#[entry_point]
+pub fn reply(
+    deps: DepsMut,
+    env: Env,
+    msg: Reply,
+) -> Result<Response, ContractError> {
+    match msg.id {
+        id if id > SETTLE_REPLY_ID_BASE => finalize_settlement(deps, env, id - SETTLE_REPLY_ID_BASE),
+        //a failing hook must never block the escrow action that triggered it
+        HOOK_REPLY_ID => match msg.result {
+            SubMsgResult::Err(error) => Ok(Response::new()
+                .add_attribute("action", "hook_failed")
+                .add_attribute("error", error)),
+            SubMsgResult::Ok(_) => Ok(Response::new()),
+        },
+        id => Err(ContractError::UnknownReplyId { id })
+    }
+}
+
+/// Wraps the hook message into one reply-on-error sub-message per registered hook
+fn prepare_escrow_hooks(store: &dyn Storage, msg: EscrowHookMsg) -> StdResult<Vec<SubMsg>> {
+    HOOKS.prepare_hooks(store, |hook| {
+        let execute = msg.clone().into_cosmos_msg(hook)?;
+        Ok(SubMsg::reply_on_error(execute, HOOK_REPLY_ID))
+    })
+}
+
+/// Builds the `wasm-escrow_*` event shared by every escrow action, so indexers
+/// can rely on one attribute schema regardless of the action type
+fn escrow_event(ty: &str, escrow: &Escrow) -> Event {
+    let price: Vec<String> = escrow.price.iter().map(|coin| coin.to_string()).collect();
+    let mut event = Event::new(ty)
+        .add_attribute("id", escrow.id.to_string())
+        .add_attribute("collection", escrow.collection.clone())
+        .add_attribute("token_id", escrow.token_id.clone())
+        .add_attribute("source", escrow.source.clone())
+        .add_attribute("recipient", escrow.recipient.clone())
+        .add_attribute("price", price.join(","));
+    //single-coin prices also name their denom on its own
+    if let [coin] = escrow.price.as_slice() {
+        event = event.add_attribute("denom", coin.denom.clone());
+    }
+    event.add_attribute("expires_at", escrow.expires_at.seconds().to_string())
+}
+
+fn next_escrow_id(store: &mut dyn Storage) -> StdResult<u64> {
+    let id = ESCROW_SEQ.may_load(store)?.unwrap_or_default() + 1;
+    ESCROW_SEQ.save(store, &id)?;
+    Ok(id)
+}
+
+/// Moves the NFT out of the escrow. With a `deliver_msg` the token is sent via
+/// `SendNft`, so a receiving contract gets a `ReceiveNft` callback carrying it.
+fn nft_transfer_msg(
+    collection: &str,
+    recipient: &str,
+    token_id: &str,
+    deliver_msg: Option<Binary>
+) -> StdResult<WasmMsg> {
+    let msg = match deliver_msg {
+        Some(msg) => Cw721ExecuteMsg::SendNft {
+            contract: recipient.to_string(),
+            token_id: token_id.to_string(),
+            msg
+        },
+        None => Cw721ExecuteMsg::TransferNft {
+            recipient: recipient.to_string(),
+            token_id: token_id.to_string()
+        }
+    };
+
+    Ok(WasmMsg::Execute { 
+        contract_addr: collection.to_string(), 
+        msg: to_binary(&msg)?, 
+        funds: vec![] 
+    })
+}
+
+fn store_escrow(store: &mut dyn Storage, escrow: &Escrow) -> StdResult<()> {
+    let key = escrow_key(&escrow.collection, &escrow.token_id);
+    match escrows().may_load(store, key.clone())? {
+        Some(old) => {
+            //an update only moves the per-party counters
+            decrement_count(store, SOURCE_COUNTS, &old.source)?;
+            decrement_count(store, RECIPIENT_COUNTS, &old.recipient)?;
+            for payee in &old.payees {
+                PAYEE_ESCROWS.remove(store, (&payee.address, &old.collection, &old.token_id));
+            }
+        },
+        None => {
+            update_stats(store, &escrow.collection, |stats| stats.active_escrows += 1)?;
+        }
+    }
+    increment_count(store, SOURCE_COUNTS, &escrow.source)?;
+    increment_count(store, RECIPIENT_COUNTS, &escrow.recipient)?;
+    for payee in &escrow.payees {
+        PAYEE_ESCROWS.save(store, (&payee.address, &escrow.collection, &escrow.token_id), &())?;
+    }
+    escrows().save(store, key, escrow)
+}
+
+
+fn remove_escrow(store: &mut dyn Storage, escrow: &Escrow) -> StdResult<()> {
+    update_stats(store, &escrow.collection, |stats| stats.active_escrows -= 1)?;
+    decrement_count(store, SOURCE_COUNTS, &escrow.source)?;
+    decrement_count(store, RECIPIENT_COUNTS, &escrow.recipient)?;
+    for payee in &escrow.payees {
+        PAYEE_ESCROWS.remove(store, (&payee.address, &escrow.collection, &escrow.token_id));
+    }
+    escrows().remove(store, escrow_key(&escrow.collection, &escrow.token_id))
+}
+
+fn increment_count(store: &mut dyn Storage, counts: Map<&str, u32>, key: &str) -> StdResult<()> {
+    counts.update(store, key, |count| -> StdResult<_> { Ok(count.unwrap_or_default() + 1) })?;
+    Ok(())
+}
+
+fn decrement_count(store: &mut dyn Storage, counts: Map<&str, u32>, key: &str) -> StdResult<()> {
+    let count = counts.may_load(store, key)?.unwrap_or_default().saturating_sub(1);
+    if count == 0 {
+        counts.remove(store, key);
+        Ok(())
+    } else {
+        counts.save(store, key, &count)
+    }
+}
+
+/// Applies the same change to the collection's and the contract-wide stats
+fn update_stats<F>(store: &mut dyn Storage, collection: &str, update: F) -> StdResult<()>
+where
+    F: Fn(&mut TradingStats),
+{
+    let mut stats = COLLECTION_STATS.may_load(store, collection)?.unwrap_or_default();
+    update(&mut stats);
+    COLLECTION_STATS.save(store, collection, &stats)?;
+
+    let mut stats = CONTRACT_STATS.may_load(store)?.unwrap_or_default();
+    update(&mut stats);
+    CONTRACT_STATS.save(store, &stats)
+}
+
+pub(crate) fn load_escrow(store: &dyn Storage, collection: &str, token_id: &str) -> Result<Escrow, ContractError> {
+    escrows()
+        .may_load(store, escrow_key(collection, token_id))?
+        .ok_or(ContractError::NoEscrow {  })
+}
+
+/// Checks shared by `execute_approve` and the `SimulateApprove` query,
+/// returns the surplus to refund
+pub(crate) fn approve_check(
+    block: &BlockInfo,
+    escrow: &Escrow,
+    sender: &str,
+    funds: &[Coin]
+) -> Result<Vec<Coin>, ContractError> {
+    if !escrow.kind.is_approvable() {
+        return Err(ContractError::WrongEscrowKind {  })
+    }
+    if escrow.payment.is_some() {
+        return Err(ContractError::AlreadyPaid {  })
+    }
+    //User can not approve after the escrow is expired
+    if escrow.is_expired(block){
+        return Err(ContractError::EscrowExpired {  })
+    }
+    //Check if the sent money covers the current price of escrow
+    let surplus = fund_check(funds, &escrow.current_price(block))?;
+
+    //the recipient or the payer it authorized, anyone for open auctions
+    if !escrow.recipient.is_empty() && sender != escrow.recipient && escrow.payer.as_deref() != Some(sender) {
+        return Err(ContractError::Unauthorized {  } )
+    }
+    Ok(surplus)
+}
+
+/// Checks shared by `execute_withdraw` and the `CanWithdraw` query
+pub(crate) fn withdraw_check(
+    store: &dyn Storage,
+    block: &BlockInfo,
+    escrow: &Escrow,
+    sender: &str
+) -> Result<(), ContractError> {
+    //auctions are closed with `Settle`, which handles the bids
+    if matches!(escrow.kind, EscrowKind::EnglishAuction { .. } | EscrowKind::SealedBidAuction { .. }) {
+        return Err(ContractError::WrongEscrowKind {  })
+    }
+    //User can withdraw after the escrow is expired
+    if !escrow.is_expired(block){
+        return Err(ContractError::EscrowNotExpired {  })
+    }
+    source_or_operator(store, block, escrow, sender)
+}
+
+/// Checks shared by `execute_cancel` and the `CanCancel` query
+pub(crate) fn cancel_check(
+    store: &dyn Storage,
+    state: &State,
+    block: &BlockInfo,
+    escrow: &Escrow,
+    sender: &str
+) -> Result<(), ContractError> {
+    //pulling an HTLC before expiry would break the swap's other leg
+    if matches!(escrow.kind, EscrowKind::Htlc { .. }) {
+        return Err(ContractError::WrongEscrowKind {  })
+    }
+    //bidders' funds are locked until the auction is settled
+    let has_bids = match escrow.kind {
+        EscrowKind::EnglishAuction { .. } => leading_bid(store, escrow.id)?.is_some(),
+        EscrowKind::SealedBidAuction { .. } => SEALED_BIDS
+            .prefix(escrow.id)
+            .range(store, None, None, cosmwasm_std::Order::Ascending)
+            .next()
+            .is_some(),
+        _ => false
+    };
+    if has_bids {
+        return Err(ContractError::AuctionHasBids {  })
+    }
+    //the admin can cancel any escrow
+    if only_owner(state, sender).is_ok() {
+        return Ok(())
+    }
+    source_or_operator(store, block, escrow, sender)
+}
+
+/// The escrow's source, or an operator the source approved that has not expired
+fn source_or_operator(
+    store: &dyn Storage,
+    block: &BlockInfo,
+    escrow: &Escrow,
+    sender: &str
+) -> Result<(), ContractError> {
+    if sender == escrow.source {
+        return Ok(())
+    }
+    let operator = OPERATORS.may_load(store, (&Addr::unchecked(&escrow.source), &Addr::unchecked(sender)))?;
+    match operator {
+        Some(expires) if !expires.is_expired(block) => Ok(()),
+        _ => Err(ContractError::Unauthorized {  } )
+    }
+}
+
+/// Coins a buyer has to send to approve the escrow at the given block
+pub(crate) fn required_funds(block: &BlockInfo, escrow: &Escrow) -> Vec<Coin> {
+    escrow.current_price(block)
+}
+
+/// The funds must cover every coin of the price basket and nothing else,
+/// returns what was paid above the price
+fn fund_check(funds: &[Coin], price: &[Coin]) -> Result<Vec<Coin>, ContractError>  {
+    let received = |denom: &str| -> Uint128 {
+        funds.iter().filter(|coin| coin.denom == denom).map(|coin| coin.amount).sum()
+    };
+
+    let mut surplus = vec![];
+    for coin in price {
+        let amount = received(&coin.denom);
+        if amount.is_zero() {
+            return Err(ContractError::MissingCoin { denom: coin.denom.clone() })
+        }
+        if amount < coin.amount {
+            return Err(ContractError::Underpayment {
+                denom: coin.denom.clone(),
+                expected: coin.amount,
+                received: amount
+            })
+        }
+        if amount > coin.amount {
+            surplus.push(Coin { denom: coin.denom.clone(), amount: amount - coin.amount });
+        }
+    }
+
+    match funds.iter().find(|coin| !coin.amount.is_zero() && price.iter().all(|price| price.denom != coin.denom)) {
+        Some(coin) => Err(ContractError::UnexpectedCoin { denom: coin.denom.clone() }),
+        None => Ok(surplus)
+    }
+}
+
+fn nonpayable(info: &MessageInfo) -> Result<(), ContractError> {
+    if !info.funds.is_empty() {
+        Err(ContractError::NonPayable{} )
+    }
+    else{
+        Ok(())
+    }
+}
+
+fn only_owner(state: &State, sender: &str) -> Result<(), ContractError> {
+    if sender != state.admin {
+        Err(ContractError::Unauthorized {  } )
     }
     else{
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -1,4 +1,5 @@
-use cosmwasm_std::StdError;
+use cosmwasm_std::{StdError, Uint128};
+use cw_controllers::HookError;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -6,6 +7,9 @@ pub enum ContractError {
     #[error("{0}")]
     Std(#[from] StdError),
 
+    #[error("{0}")]
+    Hook(#[from] HookError),
+
     #[error("Unauthorized")]
     Unauthorized {},
    
@@ -15,21 +19,148 @@ pub enum ContractError {
     #[error("This escrow is expired")]
     EscrowExpired{},
 
+    #[error("Approval has already expired")]
+    Expired{},
+
     #[error("Escrow is not expired")]
     EscrowNotExpired{  },
 
     #[error("There is no such escrow")]
     NoEscrow{},
 
-    #[error("Expected ujuno got {denom} ")]
-    NotExpectedDenom{
-        denom:String
+    #[error("This NFT is already in escrow")]
+    EscrowExists{},
+
+    #[error("This action is not supported by this kind of escrow")]
+    WrongEscrowKind{},
+
+    #[error("Invalid auction: {reason}")]
+    InvalidAuction{
+        reason: String
+    },
+
+    #[error("The auction has bids and can only be settled")]
+    AuctionHasBids{},
+
+    #[error("The auction is not in its {phase} phase")]
+    AuctionPhase{
+        phase: String
+    },
+
+    #[error("A bid has already been committed")]
+    AlreadyCommitted{},
+
+    #[error("The revealed bid does not match its commitment or deposit")]
+    InvalidReveal{},
+
+    #[error("Invalid milestones: {reason}")]
+    InvalidMilestones{
+        reason: String
     },
 
-    #[error("You should send only one coin.")]
-    OnlyOneCoinAvailable{},
+    #[error("There is no milestone {index}")]
+    NoMilestone{
+        index: u32
+    },
+
+    #[error("Milestone {index} can not be {action} in its current state")]
+    MilestoneState{
+        index: u32,
+        action: String
+    },
+
+    #[error("There is no such offer")]
+    NoOffer{},
+
+    #[error("The offer is for another NFT")]
+    OfferMismatch{},
+
+    #[error("This offer is expired")]
+    OfferExpired{},
+
+    #[error("Offer is not expired")]
+    OfferNotExpired{},
+
+    #[error("The quantity must be greater than zero")]
+    InvalidQuantity{},
+
+    #[error("The escrow has already been paid for")]
+    AlreadyPaid{},
+
+    #[error("The escrow has not been paid for")]
+    NotPaid{},
+
+    #[error("The release condition does not hold")]
+    ConditionNotMet{},
+
+    #[error("The hash lock must be a 32 byte SHA-256 hash")]
+    InvalidHashLock{},
+
+    #[error("The preimage does not match the hash lock")]
+    InvalidPreimage{},
+
+    #[error("{address} has not registered a public key")]
+    NoPubkey{
+        address: String
+    },
+
+    #[error("The offer was signed for {contract} on {chain_id}")]
+    WrongOfferDomain{
+        contract: String,
+        chain_id: String
+    },
+
+    #[error("Invalid offer signature")]
+    InvalidSignature{},
+
+    #[error("{address} does not own the NFT")]
+    NotTokenOwner{
+        address: String
+    },
+
+    #[error("Nonce {nonce} has already been used")]
+    NonceUsed{
+        nonce: u64
+    },
+
+    #[error("The price lists {denom} more than once")]
+    DuplicateDenom{
+        denom: String
+    },
+
+    #[error("Missing {denom} from the payment")]
+    MissingCoin{
+        denom: String
+    },
+
+    #[error("{denom} is not part of the price")]
+    UnexpectedCoin{
+        denom: String
+    },
+
+    #[error("Underpayment: expected {expected}{denom}, received {received}{denom}")]
+    Underpayment{
+        denom: String,
+        expected: Uint128,
+        received: Uint128
+    },
 
     #[error("This transaction does not need any payment.")]
     NonPayable{},
 
+    #[error("Settlement failed: expected {expected} to own the NFT, but it is owned by {owner}")]
+    SettlementNotVerified{
+        expected: String,
+        owner: String
+    },
+
+    #[error("Payee shares must add up to 10000 bps, got {total}")]
+    InvalidPayeeShares{
+        total: u64
+    },
+
+    #[error("Unknown reply id: {id}")]
+    UnknownReplyId{
+        id: u64
+    },
 }
@@ -1,9 +1,10 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use cosmwasm_std::{ Timestamp, Uint128};
-use cw721::Cw721ReceiveMsg;
+use cosmwasm_std::{ to_binary, Binary, Coin, CosmosMsg, Order, StdResult, Timestamp, Uint128, WasmMsg};
+use cw721::{Cw721ReceiveMsg, Expiration};
 
-use crate::state::{State, Escrow};
+use crate::error::ContractError;
+use crate::state::{State, Bid, CollectionOffer, Condition, Escrow, EscrowKind, Offer, Payee, SealedBid, TradingStats};
 
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -20,12 +21,176 @@ pub enum ExecuteMsg {
         collection: String,
         token_id: String
     },
+    /// Pay for the escrow. Callable by the recipient or its authorized payer.
+    /// With a condition that does not hold yet, the payment stays locked
+    /// until `Release`.
     Approve{
+        collection: String,
+        token_id: String,
+        /// Final owner of the NFT, defaults to the escrow recipient. An
+        /// authorized payer may not set it, nor `deliver_msg`: the NFT goes
+        /// where the recipient named in `AuthorizePayer`.
+        deliver_to: Option<String>,
+        /// When set, the NFT is delivered with `SendNft` and this payload is
+        /// passed to the recipient contract's `ReceiveNft` handler. Settlement
+        /// then only verifies that this contract no longer owns the NFT, so
+        /// the handler may forward or re-escrow it; otherwise the delivery
+        /// address must own it after the transfer.
+        deliver_msg: Option<Binary>
+    },
+    /// Settle a paid escrow once its condition holds. Callable by anyone.
+    Release{
+        collection: String,
+        token_id: String
+    },
+    /// Let another address pay for the escrow. Recipient only, `None` revokes.
+    AuthorizePayer{
+        collection: String,
+        token_id: String,
+        payer: Option<String>,
+        /// Final owner of the NFT when the payer approves, defaults to the
+        /// escrow recipient. The payer can not choose it on `Approve`.
+        #[serde(default)]
+        deliver_to: Option<String>,
+        /// Sent along with the NFT like `Approve`'s `deliver_msg`
+        #[serde(default)]
+        deliver_msg: Option<Binary>
+    },
+    /// Deliver an HTLC escrow's NFT to its recipient by revealing the
+    /// preimage of its hash lock, paying the price if it has one
+    ClaimHtlc{
+        collection: String,
+        token_id: String,
+        preimage: Binary
+    },
+    /// Bid on an English auction with the attached coin, refunding the
+    /// previously leading bidder
+    Bid{
+        collection: String,
+        token_id: String
+    },
+    /// Commit to a sealed bid during the commit phase. The attached coin is
+    /// the deposit, which has to cover the bid.
+    CommitBid{
+        collection: String,
+        token_id: String,
+        /// `sealed_bid_commitment(bidder, amount, salt)`
+        commitment: Binary
+    },
+    /// Reveal a committed sealed bid after the commit phase, before expiry
+    RevealBid{
+        collection: String,
+        token_id: String,
+        amount: Uint128,
+        salt: Binary
+    },
+    /// Close an expired English or sealed-bid auction: the winning bid buys
+    /// the NFT, otherwise the NFT goes back. Every other bid is refunded.
+    /// Callable by anyone.
+    Settle{
+        collection: String,
+        token_id: String
+    },
+    /// Hand in the work for a milestone of a paid milestone escrow. Source only.
+    SubmitMilestone{
+        collection: String,
+        token_id: String,
+        milestone: u32,
+        work: Binary
+    },
+    /// Accept the submitted work and pay the milestone out to the source.
+    /// Recipient or payer only. Releasing the last milestone delivers the NFT.
+    ApproveMilestone{
+        collection: String,
+        token_id: String,
+        milestone: u32
+    },
+    /// Hand a milestone that is not released yet to the arbiter.
+    /// Source, recipient or payer only.
+    DisputeMilestone{
+        collection: String,
+        token_id: String,
+        milestone: u32
+    },
+    /// Settle a disputed milestone. Arbiter only. Releasing pays it out like
+    /// `ApproveMilestone`, otherwise the deal ends: whatever is left of the
+    /// payment goes back to the payer and the NFT back to the source.
+    ResolveDispute{
+        collection: String,
+        token_id: String,
+        milestone: u32,
+        release: bool
+    },
+    /// Return an escrowed NFT to its source. Admin, source or its operators only.
+    /// Not available for HTLC escrows, which are withdrawn after expiry.
+    Cancel{
         collection: String,
         token_id: String
     },
+    /// Change an escrow's terms. Source or its operators only.
+    UpdateEscrow{
+        collection: String,
+        token_id: String,
+        recipient: Option<String>,
+        price: Option<Vec<Coin>>,
+        expiration: Option<Timestamp>
+    },
+    /// Allow an operator to withdraw, cancel and update all of the sender's escrows
+    ApproveAll{
+        operator: String,
+        expires: Option<Expiration>
+    },
+    /// Remove a previously granted operator
+    RevokeAll{
+        operator: String
+    },
+    /// Set the secp256k1 public key the sender signs its offers with
+    RegisterPubkey{
+        pubkey: Binary
+    },
+    /// Buy an NFT listed off-chain. The signature must be the source's over
+    /// the SHA-256 hash of the offer's JSON. The NFT is pulled from the
+    /// source, which must still own it and have approved this contract on the
+    /// collection. Callable by the offer's recipient.
+    FulfillSignedOffer{
+        offer: SignedOffer,
+        signature: Binary
+    },
+    /// Lock the sent funds as an offer for an NFT, valid until `expiration`.
+    /// The owner accepts by sending the NFT with `ReceiveNftMsg::AcceptOffer`.
+    MakeOffer{
+        collection: String,
+        token_id: String,
+        expiration: Timestamp
+    },
+    /// Take back the funds of an expired offer. Bidder only.
+    WithdrawOffer{
+        offer_id: u64
+    },
+    /// Lock `price` for each of `quantity` NFTs of a collection, one unit by
+    /// default. Anything sent above that is refunded. Holders fill it by
+    /// sending an NFT with `ReceiveNftMsg::FillCollectionOffer`.
+    MakeCollectionOffer{
+        collection: String,
+        price: Coin,
+        quantity: Option<u32>,
+        expiration: Timestamp
+    },
+    /// Take back the funds of the unfilled units of an expired collection
+    /// offer. Bidder only.
+    WithdrawCollectionOffer{
+        offer_id: u64
+    },
     ChangeConfig{
         state: State
+    },
+    /// Subscribe a contract to escrow lifecycle hooks. Admin only.
+    AddHook{
+        addr: String
+    },
+    /// Unsubscribe a contract from escrow lifecycle hooks. Admin only.
+    RemoveHook{
+        addr: String
     }
     
 }
@@ -46,6 +211,8 @@ pub enum QueryMsg {
         collection: String,
         start_after: Option<String>,
         limit: Option<u32>,
+        /// Also return each token's `NftInfo` from its collection
+        include_metadata: Option<bool>,
     },
     /// Get all escrows for a collection in reverse
     /// Return type: `EscrowsResponse`
@@ -53,22 +220,201 @@ pub enum QueryMsg {
         collection: String,
         start_before: Option<String>,
         limit: Option<u32>,
+        /// Also return each token's `NftInfo` from its collection
+        include_metadata: Option<bool>,
     },
       /// Count of all escrows
     /// Return type: `EscrowsCountResponse`
     EscrowsCount { collection: String },
+    /// Count of escrows created by a source
+    /// Return type: `EscrowsCountResponse`
+    EscrowsCountBySource { source: String },
+    /// Count of escrows awaiting a recipient
+    /// Return type: `EscrowsCountResponse`
+    EscrowsCountByRecipient { recipient: String },
     /// Get all asks by source
     /// Return type: `EscrowsResponse`
     EscrowsBySource {
         source: String,
         start_after: Option<CollectionOffset>,
         limit: Option<u32>,
+        /// Also return each token's `NftInfo` from its collection
+        include_metadata: Option<bool>,
     },
     EscrowsByRecipient{
         recipient: String,
         start_after: Option<CollectionOffset>,
         limit: Option<u32>,
-    }
+        /// Also return each token's `NftInfo` from its collection
+        include_metadata: Option<bool>,
+    },
+    /// Get escrows expiring in `[start, end)`, soonest first, optionally
+    /// filtered by source or recipient. A call walks a bounded number of
+    /// escrows, so a page may come back short; continue from
+    /// `next_start_after` while it is set.
+    /// Return type: `EscrowsByExpirationResponse`
+    EscrowsByExpiration {
+        start: Option<Timestamp>,
+        end: Option<Timestamp>,
+        source: Option<String>,
+        recipient: Option<String>,
+        start_after: Option<ExpirationOffset>,
+        limit: Option<u32>,
+        /// Also return each token's `NftInfo` from its collection
+        include_metadata: Option<bool>,
+    },
+    /// Get the non-expired fixed-price escrows of a collection listed in a
+    /// denom within a price range. A call walks a bounded number of escrows,
+    /// so a page may come back short; continue from `next_start_after` while
+    /// it is set.
+    /// Return type: `EscrowsByPriceResponse`
+    EscrowsByPrice {
+        collection: String,
+        /// Defaults to the contract denom
+        denom: Option<String>,
+        min: Option<Uint128>,
+        max: Option<Uint128>,
+        /// Cheapest first unless `descending` is given
+        order: Option<SortOrder>,
+        start_after: Option<PriceOffset>,
+        limit: Option<u32>,
+        /// Also return each token's `NftInfo` from its collection
+        include_metadata: Option<bool>,
+    },
+    /// Lowest single-coin price in a denom among the non-expired fixed-price
+    /// escrows of a collection. A call walks a bounded number of escrows; when it finds
+    /// none, continue from `next_start_after` while it is set.
+    /// Return type: `FloorPriceResponse`
+    FloorPrice {
+        collection: String,
+        /// Defaults to the contract denom
+        denom: Option<String>,
+        start_after: Option<PriceOffset>,
+    },
+    /// Search escrows with any combination of filters. The most selective
+    /// index is picked automatically and the remaining filters are applied
+    /// on top of it.
+    /// Return type: `SearchEscrowsResponse`
+    SearchEscrows {
+        filter: EscrowFilter,
+        /// Opaque `next_cursor` of the previous page
+        cursor: Option<Binary>,
+        limit: Option<u32>,
+        /// Also return each token's `NftInfo` from its collection
+        include_metadata: Option<bool>,
+    },
+    /// Trading statistics of a collection
+    /// Return type: `StatsResponse`
+    CollectionStats { collection: String },
+    /// Trading statistics over all collections
+    /// Return type: `StatsResponse`
+    ContractStats {},
+    /// Coins `sender` has to send to approve the escrow, or the error
+    /// approving would fail with
+    /// Return type: `SimulateApproveResponse`
+    SimulateApprove {
+        collection: String,
+        token_id: String,
+        sender: String,
+    },
+    /// Whether `sender` could withdraw the escrow right now
+    /// Return type: `EligibilityResponse`
+    CanWithdraw {
+        collection: String,
+        token_id: String,
+        sender: String,
+    },
+    /// Whether `sender` could cancel the escrow right now
+    /// Return type: `EligibilityResponse`
+    CanCancel {
+        collection: String,
+        token_id: String,
+        sender: String,
+    },
+    /// Operators approved by a source
+    /// Return type: `cw721::OperatorsResponse`
+    Operators {
+        source: String,
+        include_expired: Option<bool>,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Get all escrows whose proceeds go (partly) to a payee
+    /// Return type: `EscrowsResponse`
+    EscrowsByPayee {
+        payee: String,
+        start_after: Option<CollectionOffset>,
+        limit: Option<u32>,
+        /// Also return each token's `NftInfo` from its collection
+        include_metadata: Option<bool>,
+    },
+    /// Contracts subscribed to escrow lifecycle hooks
+    /// Return type: `cw_controllers::HooksResponse`
+    Hooks {},
+    /// Public key registered for signed offers
+    /// Return type: `PubkeyResponse`
+    Pubkey { address: String },
+    /// Whether a signed offer nonce of the source was already fulfilled
+    /// Return type: `NonceResponse`
+    Nonce { source: String, nonce: u64 },
+    /// Preimage revealed for a hash lock by an HTLC claim
+    /// Return type: `PreimageResponse`
+    Preimage { hash_lock: Binary },
+    /// Price a buyer would pay for the escrow right now
+    /// Return type: `CurrentPriceResponse`
+    CurrentPrice { collection: String, token_id: String },
+    /// Bid history of an English auction, oldest first
+    /// Return type: `BidsResponse`
+    Bids {
+        escrow_id: u64,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Committed bids of a sealed-bid auction, by bidder
+    /// Return type: `SealedBidsResponse`
+    SealedBids {
+        escrow_id: u64,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Return type: `OfferResponse`
+    Offer { offer_id: u64 },
+    /// Offers a bidder made, by id
+    /// Return type: `OffersResponse`
+    OffersByBidder {
+        bidder: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Offers made for an NFT, by id
+    /// Return type: `OffersResponse`
+    OffersByToken {
+        collection: String,
+        token_id: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Return type: `CollectionOfferResponse`
+    CollectionOffer { offer_id: u64 },
+    /// Best non-expired offers for a collection in a denom, highest price
+    /// first and older offers first on a tie. A call walks a bounded number
+    /// of offers, so a page may come back short; continue from
+    /// `next_start_after` while it is set.
+    /// Return type: `BestCollectionOffersResponse`
+    CollectionOffers {
+        collection: String,
+        /// Defaults to the contract denom
+        denom: Option<String>,
+        start_after: Option<CollectionOfferOffset>,
+        limit: Option<u32>,
+    },
+    /// Collection offers a bidder made, by id
+    /// Return type: `CollectionOffersResponse`
+    CollectionOffersByBidder {
+        bidder: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
 }
 
 /// Offset for collection pagination
@@ -78,20 +424,310 @@ pub struct CollectionOffset {
     pub token_id: String,
 }
 
+/// Offset for expiration pagination
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ExpirationOffset {
+    pub expires_at: Timestamp,
+    pub collection: String,
+    pub token_id: String,
+}
+
+/// Offset for paginating the best collection offers
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollectionOfferOffset {
+    pub price: Uint128,
+    pub offer_id: u64,
+}
+
+/// Offset for price pagination within a collection
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PriceOffset {
+    pub price: Uint128,
+    pub token_id: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+impl From<SortOrder> for Order {
+    fn from(order: SortOrder) -> Order {
+        match order {
+            SortOrder::Ascending => Order::Ascending,
+            SortOrder::Descending => Order::Descending,
+        }
+    }
+}
+
+/// Filters of `SearchEscrows`, all optional and combined with AND
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct EscrowFilter {
+    pub collection: Option<String>,
+    pub source: Option<String>,
+    pub recipient: Option<String>,
+    /// Denom the price filters apply to, defaults to the contract denom.
+    /// With a price filter, only fixed-price escrows match.
+    pub denom: Option<String>,
+    pub min_price: Option<Uint128>,
+    pub max_price: Option<Uint128>,
+    pub status: Option<EscrowStatus>,
+    /// Only escrows expiring at or after this time
+    pub expires_after: Option<Timestamp>,
+    /// Only escrows expiring before this time
+    pub expires_before: Option<Timestamp>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EscrowStatus {
+    Active,
+    Expired,
+}
+
 /// Escrow infos
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct EscrowInfoMsg {
+    /// Auctions may leave it empty to accept any buyer. English and
+    /// sealed-bid auctions must, as they go to the winning bidder.
     pub recipient: String,
-    pub price: Uint128,
+    /// One coin per denom, the first one is the listed price. May be empty
+    /// for HTLC escrows paid for on another chain.
+    pub price: Vec<Coin>,
     pub expiration: Timestamp,
+    /// Split the proceeds among these payees, shares must add up to 10000 bps
+    #[serde(default)]
+    pub payees: Vec<Payee>,
+    #[serde(default)]
+    pub kind: EscrowKind,
+    /// Only settle once this query answers `true`
+    #[serde(default)]
+    pub condition: Option<Condition>,
+}
+
+/// `ReceiveNft` payloads besides `EscrowInfoMsg`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiveNftMsg {
+    /// Sell the NFT to the bidder of an offer for it
+    AcceptOffer { offer_id: u64 },
+    /// Sell the NFT to a collection offer for its collection
+    FillCollectionOffer { offer_id: u64 },
+}
+
+/// Escrow terms signed off-chain by the NFT's owner
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SignedOffer {
+    /// The escrow contract and chain the offer is good for, so a signature
+    /// can not be replayed against another deployment
+    pub contract: String,
+    pub chain_id: String,
+    pub source: String,
+    pub collection: String,
+    pub token_id: String,
+    pub recipient: String,
+    pub price: Vec<Coin>,
+    pub expires_at: Timestamp,
+    /// Unique per source, an offer can be fulfilled only once
+    pub nonce: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PubkeyResponse { pub pubkey: Option<Binary> }
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NonceResponse { pub used: bool }
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PreimageResponse { pub preimage: Option<Binary> }
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CurrentPriceResponse { pub price: Vec<Coin> }
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BidsResponse { pub bids: Vec<Bid> }
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SealedBidsResponse { pub bids: Vec<SealedBid> }
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OfferResponse { pub offer: Option<Offer> }
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OffersResponse { pub offers: Vec<Offer> }
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollectionOfferResponse { pub offer: Option<CollectionOffer> }
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollectionOffersResponse { pub offers: Vec<CollectionOffer> }
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BestCollectionOffersResponse {
+    pub offers: Vec<CollectionOffer>,
+    /// Where to resume, `None` once every offer has been walked
+    pub next_start_after: Option<CollectionOfferOffset>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct EscrowResponse {  pub escrow: Option<Escrow> }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
-pub struct EscrowsResponse { pub escrows: Vec<Escrow> }
+pub struct EscrowsResponse {
+    pub escrows: Vec<Escrow>,
+    /// With `include_metadata`, one entry per escrow, `None` where the
+    /// collection did not answer
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub metadata: Vec<Option<NftMetadata>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EscrowsByExpirationResponse {
+    pub escrows: Vec<Escrow>,
+    /// With `include_metadata`, one entry per escrow, `None` where the
+    /// collection did not answer
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub metadata: Vec<Option<NftMetadata>>,
+    /// Where to resume, `None` once every escrow in range has been walked
+    pub next_start_after: Option<ExpirationOffset>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EscrowsByPriceResponse {
+    pub escrows: Vec<Escrow>,
+    /// With `include_metadata`, one entry per escrow, `None` where the
+    /// collection did not answer
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub metadata: Vec<Option<NftMetadata>>,
+    /// Where to resume, `None` once every escrow in range has been walked
+    pub next_start_after: Option<PriceOffset>,
+}
+
+/// Token metadata as reported by the collection's `NftInfo` query
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NftMetadata {
+    pub token_uri: Option<String>,
+    /// Raw JSON `NftInfo` response, including the collection-specific extension
+    pub nft_info: Binary,
+}
 
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct EscrowsCountResponse { pub count: u32 }
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EscrowSearchResult {
+    pub escrow: Escrow,
+    pub is_expired: bool,
+    /// Seconds until the escrow expires, zero once expired
+    pub seconds_remaining: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<NftMetadata>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SearchEscrowsResponse {
+    pub escrows: Vec<EscrowSearchResult>,
+    /// Pass back as `cursor` to fetch the next page, `None` on the last page
+    pub next_cursor: Option<Binary>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StatsResponse {
+    pub active_escrows: u64,
+    pub settlements: u64,
+    /// Total settled volume per denom
+    pub volume: Vec<Coin>,
+    pub average_price: Vec<Coin>,
+    /// Price of the last settlement, empty before the first one
+    pub last_sale: Vec<Coin>,
+    pub all_time_high: Vec<Coin>,
+}
+
+impl From<TradingStats> for StatsResponse {
+    fn from(stats: TradingStats) -> StatsResponse {
+        let coins = |f: fn(&crate::state::DenomVolume) -> Uint128| {
+            stats.volume.iter().map(|v| Coin { denom: v.denom.clone(), amount: f(v) }).collect::<Vec<_>>()
+        };
+        StatsResponse {
+            active_escrows: stats.active_escrows,
+            settlements: stats.settlements,
+            volume: coins(|v| v.amount),
+            average_price: coins(|v| v.amount / Uint128::from(v.settlements)),
+            last_sale: stats.last_sale.clone(),
+            all_time_high: coins(|v| v.all_time_high),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulateApproveResponse {
+    /// Coins to attach to `Approve`, anything above is refunded
+    pub required: Vec<Coin>,
+    /// Error `Approve` would currently fail with, if any
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EligibilityResponse {
+    pub allowed: bool,
+    /// Error the action would currently fail with, if any
+    pub error: Option<String>,
+}
+
+impl From<Result<(), ContractError>> for EligibilityResponse {
+    fn from(result: Result<(), ContractError>) -> EligibilityResponse {
+        EligibilityResponse {
+            allowed: result.is_ok(),
+            error: result.err().map(|err| err.to_string()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FloorPriceResponse {
+    /// `None` when the collection has no active escrow in the denom
+    pub price: Option<Coin>,
+    pub escrow: Option<Escrow>,
+    /// Set when the walk stopped before finding an escrow, resume from here
+    pub next_start_after: Option<PriceOffset>,
+}
+
+/// Lifecycle notification sent to every registered hook contract
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EscrowHookMsg {
+    EscrowCreated { escrow: Escrow },
+    EscrowSettled { escrow: Escrow, buyer: String },
+    EscrowWithdrawn { escrow: Escrow },
+    EscrowCancelled { escrow: Escrow },
+}
+
+impl EscrowHookMsg {
+    /// serializes the message, wrapped in `EscrowHookExecuteMsg`
+    pub fn into_binary(self) -> StdResult<Binary> {
+        let msg = EscrowHookExecuteMsg::EscrowHook(self);
+        to_binary(&msg)
+    }
+
+    /// creates a cosmos_msg sending this struct to the named contract
+    pub fn into_cosmos_msg<T: Into<String>>(self, contract_addr: T) -> StdResult<CosmosMsg> {
+        let msg = self.into_binary()?;
+        let execute = WasmMsg::Execute {
+            contract_addr: contract_addr.into(),
+            msg,
+            funds: vec![],
+        };
+        Ok(execute.into())
+    }
+}
+
+/// This is just a helper to properly serialize the above message.
+/// Hook receivers should accept `{"escrow_hook": {...}}` in their `ExecuteMsg`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+enum EscrowHookExecuteMsg {
+    EscrowHook(EscrowHookMsg),
+}
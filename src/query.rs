@@ -1,15 +1,28 @@
-use crate::msg::{ EscrowsCountResponse,  EscrowResponse, EscrowsResponse, QueryMsg, CollectionOffset };
-use crate::state::{  State, CONFIG, escrows, escrow_key };
-use cosmwasm_std::{entry_point, to_binary, Binary, Deps, Env, Order, StdResult};
-use cw_storage_plus::Bound;
+use crate::msg::{ EscrowsCountResponse,  EscrowResponse, EscrowsResponse, QueryMsg, CollectionOffset, ExpirationOffset,
+    FloorPriceResponse, PriceOffset, SortOrder, EscrowFilter, EscrowStatus, EscrowSearchResult,
+    SearchEscrowsResponse, StatsResponse, SimulateApproveResponse, EligibilityResponse,
+    NftMetadata, PubkeyResponse, NonceResponse, PreimageResponse,
+    CurrentPriceResponse, BidsResponse, SealedBidsResponse, OfferResponse, OffersResponse,
+    CollectionOfferOffset, CollectionOfferResponse, CollectionOffersResponse, BestCollectionOffersResponse,
+    EscrowsByPriceResponse, EscrowsByExpirationResponse };
+use crate::contract::{ load_escrow, approve_check, withdraw_check, cancel_check, required_funds };
+use crate::state::{  State, CONFIG, OPERATORS, PUBKEYS, USED_NONCES, REVEALED_PREIMAGES, BIDS, SEALED_BIDS, offers, collection_offers, PAYEE_ESCROWS, COLLECTION_STATS, CONTRACT_STATS, SOURCE_COUNTS, RECIPIENT_COUNTS, escrows, escrow_key, price_key, Escrow, HOOKS, Order as _ };
+use cosmwasm_std::{entry_point, from_binary, to_binary, to_vec, Binary, ContractResult, Deps, Empty, Env, Order,
+    QueryRequest, StdResult, SystemResult, Timestamp, Uint128, WasmQuery};
+use cw721::{Approval, Cw721QueryMsg, OperatorsResponse};
+use cw_storage_plus::{Bound, PrefixBound, PrimaryKey};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 
 // Query limits
 const DEFAULT_QUERY_LIMIT: u32 = 10;
 const MAX_QUERY_LIMIT: u32 = 30;
+// Most entries a filtered query walks per call, however few of them match
+const MAX_SCAN_LIMIT: usize = 100;
 
 
 #[entry_point]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::GetStateInfo {} => to_binary(&query_state_info(deps)?),
         QueryMsg::Escrow {
@@ -20,49 +33,252 @@ pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
             collection,
             start_after,
             limit,
+            include_metadata,
         } => to_binary(&query_escrows(
             deps,
             collection,
             start_after,
             limit,
+            include_metadata,
         )?),
         QueryMsg::ReverseEscrows{
             collection,
             start_before,
             limit,
+            include_metadata,
         } => to_binary(&reverse_query_escrows(
             deps,
             collection,
             start_before,
             limit,
+            include_metadata,
         )?),
         QueryMsg::EscrowsBySource {
             source,
             start_after,
             limit,
+            include_metadata,
         } => to_binary(&query_escrows_by_source(
             deps,
             source,
             start_after,
             limit,
+            include_metadata,
         )?),
         QueryMsg::EscrowsByRecipient {
             recipient,
             start_after,
             limit,
+            include_metadata,
         } => to_binary(&query_escrows_by_recipient(
             deps,
             recipient,
             start_after,
             limit,
+            include_metadata,
         )?),
         QueryMsg::EscrowsCount { collection } => {
             to_binary(&query_escrows_count(deps, collection)?)
         },
+        QueryMsg::EscrowsCountBySource { source } => {
+            to_binary(&query_escrows_count_by_source(deps, source)?)
+        },
+        QueryMsg::EscrowsCountByRecipient { recipient } => {
+            to_binary(&query_escrows_count_by_recipient(deps, recipient)?)
+        },
+        QueryMsg::EscrowsByExpiration {
+            start,
+            end,
+            source,
+            recipient,
+            start_after,
+            limit,
+            include_metadata,
+        } => to_binary(&query_escrows_by_expiration(
+            deps,
+            start,
+            end,
+            source,
+            recipient,
+            start_after,
+            limit,
+            include_metadata,
+        )?),
+        QueryMsg::EscrowsByPrice {
+            collection,
+            denom,
+            min,
+            max,
+            order,
+            start_after,
+            limit,
+            include_metadata,
+        } => to_binary(&query_escrows_by_price(
+            deps,
+            env,
+            collection,
+            denom,
+            min,
+            max,
+            order,
+            start_after,
+            limit,
+            include_metadata,
+        )?),
+        QueryMsg::FloorPrice { collection, denom, start_after } => {
+            to_binary(&query_floor_price(deps, env, collection, denom, start_after)?)
+        },
+        QueryMsg::SearchEscrows {
+            filter,
+            cursor,
+            limit,
+            include_metadata,
+        } => to_binary(&query_search_escrows(deps, env, filter, cursor, limit, include_metadata)?),
+        QueryMsg::CollectionStats { collection } => {
+            to_binary(&query_collection_stats(deps, collection)?)
+        },
+        QueryMsg::ContractStats {} => to_binary(&query_contract_stats(deps)?),
+        QueryMsg::SimulateApprove {
+            collection,
+            token_id,
+            sender,
+        } => to_binary(&query_simulate_approve(deps, env, collection, token_id, sender)?),
+        QueryMsg::CanWithdraw {
+            collection,
+            token_id,
+            sender,
+        } => to_binary(&query_can_withdraw(deps, env, collection, token_id, sender)?),
+        QueryMsg::CanCancel {
+            collection,
+            token_id,
+            sender,
+        } => to_binary(&query_can_cancel(deps, env, collection, token_id, sender)?),
+        QueryMsg::Operators {
+            source,
+            include_expired,
+            start_after,
+            limit,
+        } => to_binary(&query_operators(
+            deps,
+            env,
+            source,
+            include_expired.unwrap_or(false),
+            start_after,
+            limit,
+        )?),
+        QueryMsg::EscrowsByPayee {
+            payee,
+            start_after,
+            limit,
+            include_metadata,
+        } => to_binary(&query_escrows_by_payee(
+            deps,
+            payee,
+            start_after,
+            limit,
+            include_metadata,
+        )?),
+        QueryMsg::Hooks {} => to_binary(&HOOKS.query_hooks(deps)?),
+        QueryMsg::Pubkey { address } => to_binary(&query_pubkey(deps, address)?),
+        QueryMsg::Nonce { source, nonce } => to_binary(&query_nonce(deps, source, nonce)?),
+        QueryMsg::Preimage { hash_lock } => to_binary(&query_preimage(deps, hash_lock)?),
+        QueryMsg::CurrentPrice {
+            collection,
+            token_id,
+        } => to_binary(&query_current_price(deps, env, collection, token_id)?),
+        QueryMsg::Bids {
+            escrow_id,
+            start_after,
+            limit,
+        } => to_binary(&query_bids(deps, escrow_id, start_after, limit)?),
+        QueryMsg::SealedBids {
+            escrow_id,
+            start_after,
+            limit,
+        } => to_binary(&query_sealed_bids(deps, escrow_id, start_after, limit)?),
+        QueryMsg::Offer { offer_id } => to_binary(&query_offer(deps, offer_id)?),
+        QueryMsg::OffersByBidder {
+            bidder,
+            start_after,
+            limit,
+        } => to_binary(&query_offers_by_bidder(deps, bidder, start_after, limit)?),
+        QueryMsg::OffersByToken {
+            collection,
+            token_id,
+            start_after,
+            limit,
+        } => to_binary(&query_offers_by_token(deps, collection, token_id, start_after, limit)?),
+        QueryMsg::CollectionOffer { offer_id } => to_binary(&query_collection_offer(deps, offer_id)?),
+        QueryMsg::CollectionOffers {
+            collection,
+            denom,
+            start_after,
+            limit,
+        } => to_binary(&query_collection_offers(deps, env, collection, denom, start_after, limit)?),
+        QueryMsg::CollectionOffersByBidder {
+            bidder,
+            start_after,
+            limit,
+        } => to_binary(&query_collection_offers_by_bidder(deps, bidder, start_after, limit)?),
      
     }
 }
 
+/// Keeps up to `limit` entries matching `keep` while walking at most
+/// `MAX_SCAN_LIMIT` entries. When the walk stops before running out of
+/// entries, the last walked one is returned as well to resume after it.
+fn scan_page<T: Clone>(
+    entries: impl Iterator<Item = StdResult<T>>,
+    limit: usize,
+    keep: impl Fn(&T) -> bool,
+) -> StdResult<(Vec<T>, Option<T>)> {
+    let mut page = vec![];
+    for (walked, entry) in entries.enumerate() {
+        let entry = entry?;
+        let kept = keep(&entry);
+        let last = (page.len() + kept as usize == limit || walked + 1 == MAX_SCAN_LIMIT).then(|| entry.clone());
+        if kept {
+            page.push(entry);
+        }
+        if last.is_some() {
+            return Ok((page, last));
+        }
+    }
+    Ok((page, None))
+}
+
+/// Looks up the metadata of every escrow when requested, empty otherwise
+fn escrows_metadata(deps: Deps, escrows: &[Escrow], include_metadata: Option<bool>) -> Vec<Option<NftMetadata>> {
+    match include_metadata {
+        Some(true) => escrows.iter().map(|escrow| nft_metadata(deps, escrow)).collect(),
+        _ => vec![],
+    }
+}
+
+/// Queries the collection for the escrowed token's `NftInfo`. A collection
+/// that errors or answers with something unexpected yields `None` instead
+/// of failing the whole query.
+fn nft_metadata(deps: Deps, escrow: &Escrow) -> Option<NftMetadata> {
+    let msg = to_binary(&Cw721QueryMsg::NftInfo { token_id: escrow.token_id.clone() }).ok()?;
+    let request: QueryRequest<Empty> = QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: escrow.collection.clone(),
+        msg,
+    });
+    let nft_info = match deps.querier.raw_query(&to_vec(&request).ok()?) {
+        SystemResult::Ok(ContractResult::Ok(nft_info)) => nft_info,
+        _ => return None,
+    };
+    let info: TokenUri = from_binary(&nft_info).ok()?;
+
+    Some(NftMetadata { token_uri: info.token_uri, nft_info })
+}
+
+/// The part of `NftInfoResponse` that does not depend on the extension type
+#[derive(Deserialize)]
+struct TokenUri {
+    token_uri: Option<String>,
+}
+
 pub fn query_state_info(deps:Deps) -> StdResult<State>{
     let state =  CONFIG.load(deps.storage)?;
     Ok(state)
@@ -80,6 +296,7 @@ pub fn query_escrows(
     collection: String,
     start_after: Option<String>,
     limit: Option<u32>,
+    include_metadata: Option<bool>,
 ) -> StdResult<EscrowsResponse> {
     let limit = limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT) as usize;
 
@@ -100,7 +317,9 @@ pub fn query_escrows(
         .map(|res| res.map(|item| item.1))
         .collect::<StdResult<Vec<_>>>()?;
 
-    Ok(EscrowsResponse { escrows })
+    let metadata = escrows_metadata(deps, &escrows, include_metadata);
+
+    Ok(EscrowsResponse { escrows, metadata })
 }
 
 pub fn reverse_query_escrows(
@@ -108,6 +327,7 @@ pub fn reverse_query_escrows(
     collection: String,
     start_before: Option<String>,
     limit: Option<u32>,
+    include_metadata: Option<bool>,
 ) -> StdResult<EscrowsResponse> {
     let limit = limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT) as usize;
 
@@ -128,25 +348,45 @@ pub fn reverse_query_escrows(
         .map(|res| res.map(|item| item.1))
         .collect::<StdResult<Vec<_>>>()?;
 
-    Ok(EscrowsResponse { escrows })
+    let metadata = escrows_metadata(deps, &escrows, include_metadata);
+
+    Ok(EscrowsResponse { escrows, metadata })
 }
 
 pub fn query_escrows_count(deps: Deps, collection: String) -> StdResult<EscrowsCountResponse> {
-    let count = escrows()
-        .idx
-        .collection
-        .prefix(collection)
-        .keys_raw(deps.storage, None, None, Order::Ascending)
-        .count() as u32;
+    let stats = COLLECTION_STATS.may_load(deps.storage, &collection)?.unwrap_or_default();
+
+    Ok(EscrowsCountResponse { count: stats.active_escrows as u32 })
+}
+
+pub fn query_escrows_count_by_source(deps: Deps, source: String) -> StdResult<EscrowsCountResponse> {
+    let count = SOURCE_COUNTS.may_load(deps.storage, &source)?.unwrap_or_default();
+
+    Ok(EscrowsCountResponse { count })
+}
+
+pub fn query_escrows_count_by_recipient(deps: Deps, recipient: String) -> StdResult<EscrowsCountResponse> {
+    let count = RECIPIENT_COUNTS.may_load(deps.storage, &recipient)?.unwrap_or_default();
 
     Ok(EscrowsCountResponse { count })
 }
 
+pub fn query_collection_stats(deps: Deps, collection: String) -> StdResult<StatsResponse> {
+    let stats = COLLECTION_STATS.may_load(deps.storage, &collection)?.unwrap_or_default();
+    Ok(stats.into())
+}
+
+pub fn query_contract_stats(deps: Deps) -> StdResult<StatsResponse> {
+    let stats = CONTRACT_STATS.may_load(deps.storage)?.unwrap_or_default();
+    Ok(stats.into())
+}
+
 pub fn query_escrows_by_source(
     deps: Deps,
     source: String,
     start_after: Option<CollectionOffset>,
     limit: Option<u32>,
+    include_metadata: Option<bool>,
 ) -> StdResult<EscrowsResponse> {
     let limit = limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT) as usize;
 
@@ -167,7 +407,9 @@ pub fn query_escrows_by_source(
         .map(|res| res.map(|item| item.1))
         .collect::<StdResult<Vec<_>>>()?;
 
-    Ok(EscrowsResponse { escrows })
+    let metadata = escrows_metadata(deps, &escrows, include_metadata);
+
+    Ok(EscrowsResponse { escrows, metadata })
 }
 
 
@@ -176,6 +418,7 @@ pub fn query_escrows_by_recipient(
     recipient: String,
     start_after: Option<CollectionOffset>,
     limit: Option<u32>,
+    include_metadata: Option<bool>,
 ) -> StdResult<EscrowsResponse> {
     let limit = limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT) as usize;
 
@@ -196,6 +439,599 @@ pub fn query_escrows_by_recipient(
         .map(|res| res.map(|item| item.1))
         .collect::<StdResult<Vec<_>>>()?;
 
-    Ok(EscrowsResponse { escrows })
+    let metadata = escrows_metadata(deps, &escrows, include_metadata);
+
+    Ok(EscrowsResponse { escrows, metadata })
+}
+
+
+
+pub fn query_escrows_by_payee(
+    deps: Deps,
+    payee: String,
+    start_after: Option<CollectionOffset>,
+    limit: Option<u32>,
+    include_metadata: Option<bool>,
+) -> StdResult<EscrowsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT) as usize;
+
+    let start = start_after
+        .as_ref()
+        .map(|start| Bound::exclusive((start.collection.as_str(), start.token_id.as_str())));
+
+    let escrows = PAYEE_ESCROWS
+        .sub_prefix(&payee)
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|res| res.and_then(|(collection, token_id)| {
+            escrows().load(deps.storage, escrow_key(&collection, &token_id))
+        }))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let metadata = escrows_metadata(deps, &escrows, include_metadata);
+
+    Ok(EscrowsResponse { escrows, metadata })
 }
 
+#[allow(clippy::too_many_arguments)]
+pub fn query_escrows_by_expiration(
+    deps: Deps,
+    start: Option<Timestamp>,
+    end: Option<Timestamp>,
+    source: Option<String>,
+    recipient: Option<String>,
+    start_after: Option<ExpirationOffset>,
+    limit: Option<u32>,
+    include_metadata: Option<bool>,
+) -> StdResult<EscrowsByExpirationResponse> {
+    let limit = limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT) as usize;
+
+    // resume from the cursor's expiration, skipping what was already returned there
+    let lower = match (&start, &start_after) {
+        (Some(start), Some(offset)) => Some(start.nanos().max(offset.expires_at.nanos())),
+        (Some(start), None) => Some(start.nanos()),
+        (None, Some(offset)) => Some(offset.expires_at.nanos()),
+        (None, None) => None,
+    };
+    // entries sharing an expiration are ordered by their raw primary key
+    let cursor = start_after.map(|offset| {
+        (offset.expires_at.nanos(), escrow_key(&offset.collection, &offset.token_id).joined_key())
+    });
+
+    let index = escrows();
+    let entries = index
+        .idx
+        .expires_at
+        .prefix_range_raw(
+            deps.storage,
+            lower.map(PrefixBound::inclusive),
+            end.map(|end| PrefixBound::exclusive(end.nanos())),
+            Order::Ascending,
+        )
+        .filter(|res| match (res, &cursor) {
+            (Ok((pk, escrow)), Some((expires_at, last))) => escrow.expires_at.nanos() != *expires_at || pk > last,
+            _ => true,
+        })
+        .map(|res| res.map(|item| item.1));
+    let (escrows, last) = scan_page(entries, limit, |escrow| {
+        source.as_ref().is_none_or(|source| &escrow.source == source)
+            && recipient.as_ref().is_none_or(|recipient| &escrow.recipient == recipient)
+    })?;
+
+    let metadata = escrows_metadata(deps, &escrows, include_metadata);
+    let next_start_after = last.map(|escrow| ExpirationOffset {
+        expires_at: escrow.expires_at,
+        collection: escrow.collection,
+        token_id: escrow.token_id,
+    });
+
+    Ok(EscrowsByExpirationResponse { escrows, metadata, next_start_after })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn query_escrows_by_price(
+    deps: Deps,
+    env: Env,
+    collection: String,
+    denom: Option<String>,
+    min: Option<Uint128>,
+    max: Option<Uint128>,
+    order: Option<SortOrder>,
+    start_after: Option<PriceOffset>,
+    limit: Option<u32>,
+    include_metadata: Option<bool>,
+) -> StdResult<EscrowsByPriceResponse> {
+    let limit = limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT) as usize;
+    let order: Order = order.unwrap_or(SortOrder::Ascending).into();
+    let denom = match denom {
+        Some(denom) => denom,
+        None => CONFIG.load(deps.storage)?.denom,
+    };
+
+    let mut min = min.unwrap_or_default();
+    let mut max = max.unwrap_or(Uint128::MAX);
+    // resume from the cursor's price, skipping what was already returned there
+    if let Some(offset) = &start_after {
+        match order {
+            Order::Ascending => min = min.max(offset.price),
+            Order::Descending => max = max.min(offset.price),
+        }
+    }
+
+    let index = escrows();
+    let entries = index
+        .idx
+        .collection_price
+        .prefix_range_raw(
+            deps.storage,
+            Some(PrefixBound::inclusive((collection.clone(), denom.clone(), price_key(min)))),
+            Some(PrefixBound::inclusive((collection, denom, price_key(max)))),
+            order,
+        )
+        .map(|res| res.map(|item| item.1))
+        .filter(|res| match (res, &start_after) {
+            (Ok(escrow), Some(offset)) => {
+                escrow.listed_price().map(|price| price.amount) != Some(offset.price)
+                    || match order {
+                        Order::Ascending => escrow.token_id > offset.token_id,
+                        Order::Descending => escrow.token_id < offset.token_id,
+                    }
+            }
+            _ => true,
+        });
+    let (escrows, last) = scan_page(entries, limit, |escrow| !escrow.is_expired(&env.block))?;
+
+    let metadata = escrows_metadata(deps, &escrows, include_metadata);
+    let next_start_after = last.map(|escrow| price_offset(&escrow));
+
+    Ok(EscrowsByPriceResponse { escrows, metadata, next_start_after })
+}
+
+fn price_offset(escrow: &Escrow) -> PriceOffset {
+    PriceOffset {
+        price: escrow.listed_price().map(|price| price.amount).unwrap_or_default(),
+        token_id: escrow.token_id.clone(),
+    }
+}
+
+pub fn query_floor_price(
+    deps: Deps,
+    env: Env,
+    collection: String,
+    denom: Option<String>,
+    start_after: Option<PriceOffset>,
+) -> StdResult<FloorPriceResponse> {
+    let denom = match denom {
+        Some(denom) => denom,
+        None => CONFIG.load(deps.storage)?.denom,
+    };
+    let min = start_after.as_ref().map(|offset| offset.price).unwrap_or_default();
+
+    let index = escrows();
+    let entries = index
+        .idx
+        .collection_price
+        .prefix_range_raw(
+            deps.storage,
+            Some(PrefixBound::inclusive((collection.clone(), denom.clone(), price_key(min)))),
+            Some(PrefixBound::inclusive((collection, denom, price_key(Uint128::MAX)))),
+            Order::Ascending,
+        )
+        .map(|res| res.map(|item| item.1))
+        .filter(|res| match (res, &start_after) {
+            (Ok(escrow), Some(offset)) => {
+                escrow.listed_price().map(|price| price.amount) != Some(offset.price)
+                    || escrow.token_id > offset.token_id
+            }
+            _ => true,
+        });
+    // baskets asking for other coins on top are not comparable, skip them
+    let (page, last) = scan_page(entries, 1, |escrow| escrow.price.len() == 1 && !escrow.is_expired(&env.block))?;
+    let escrow = page.into_iter().next();
+    let next_start_after = match &escrow {
+        Some(_) => None,
+        None => last.map(|escrow| price_offset(&escrow)),
+    };
+
+    Ok(FloorPriceResponse {
+        price: escrow.as_ref().and_then(|escrow| escrow.listed_price().cloned()),
+        escrow,
+        next_start_after,
+    })
+}
+
+/// Position of the last returned escrow. It carries every value any of the
+/// search indexes is ordered by, so a page can resume whichever index is used.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+struct SearchCursor {
+    collection: String,
+    token_id: String,
+    price: Uint128,
+    expires_at: Timestamp,
+}
+
+pub fn query_search_escrows(
+    deps: Deps,
+    env: Env,
+    filter: EscrowFilter,
+    cursor: Option<Binary>,
+    limit: Option<u32>,
+    include_metadata: Option<bool>,
+) -> StdResult<SearchEscrowsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT) as usize;
+    let cursor: Option<SearchCursor> = cursor.map(|cursor| from_binary(&cursor)).transpose()?;
+    let start = cursor
+        .as_ref()
+        .map(|cursor| Bound::exclusive(escrow_key(&cursor.collection, &cursor.token_id)));
+    let now = env.block.time;
+    let denom = match &filter.denom {
+        Some(denom) => denom.clone(),
+        None => CONFIG.load(deps.storage)?.denom,
+    };
+    let price_filter = filter.min_price.is_some() || filter.max_price.is_some();
+
+    // the expiry window, narrowed by the status filter
+    let mut expires_after = filter.expires_after;
+    let mut expires_before = filter.expires_before;
+    match filter.status {
+        Some(EscrowStatus::Active) => {
+            // an escrow expiring exactly now is already expired
+            let after = Timestamp::from_nanos(now.nanos() + 1);
+            expires_after = Some(expires_after.map_or(after, |t| t.max(after)));
+        }
+        Some(EscrowStatus::Expired) => {
+            let before = Timestamp::from_nanos(now.nanos() + 1);
+            expires_before = Some(expires_before.map_or(before, |t| t.min(before)));
+        }
+        None => {}
+    }
+
+    let index = escrows();
+    // pick the most selective index for the given filters
+    let candidates: Box<dyn Iterator<Item = StdResult<Escrow>>> = if let Some(source) = &filter.source {
+        Box::new(index.idx.source.prefix(source.clone())
+            .range(deps.storage, start, None, Order::Ascending)
+            .map(|res| res.map(|item| item.1)))
+    } else if let Some(recipient) = &filter.recipient {
+        Box::new(index.idx.recipient.prefix(recipient.clone())
+            .range(deps.storage, start, None, Order::Ascending)
+            .map(|res| res.map(|item| item.1)))
+    } else if let Some(collection) = &filter.collection {
+        if price_filter {
+            let mut min = filter.min_price.unwrap_or_default();
+            if let Some(cursor) = &cursor {
+                min = min.max(cursor.price);
+            }
+            let max = filter.max_price.unwrap_or(Uint128::MAX);
+            let last = cursor.clone();
+            Box::new(index.idx.collection_price
+                .prefix_range_raw(
+                    deps.storage,
+                    Some(PrefixBound::inclusive((collection.clone(), denom.clone(), price_key(min)))),
+                    Some(PrefixBound::inclusive((collection.clone(), denom.clone(), price_key(max)))),
+                    Order::Ascending,
+                )
+                .map(|res| res.map(|item| item.1))
+                .filter(move |res| match (res, &last) {
+                    (Ok(escrow), Some(last)) => {
+                        escrow.listed_price().map(|price| price.amount) != Some(last.price)
+                            || escrow.token_id > last.token_id
+                    }
+                    _ => true,
+                }))
+        } else {
+            Box::new(index.idx.collection.prefix(collection.clone())
+                .range(deps.storage, start, None, Order::Ascending)
+                .map(|res| res.map(|item| item.1)))
+        }
+    } else if expires_after.is_some() || expires_before.is_some() {
+        let mut after = expires_after.map(|t| t.nanos());
+        if let Some(cursor) = &cursor {
+            after = Some(after.unwrap_or_default().max(cursor.expires_at.nanos()));
+        }
+        // entries sharing an expiration are ordered by their raw primary key
+        let last = cursor
+            .as_ref()
+            .map(|cursor| (cursor.expires_at, escrow_key(&cursor.collection, &cursor.token_id).joined_key()));
+        Box::new(index.idx.expires_at
+            .prefix_range_raw(
+                deps.storage,
+                after.map(PrefixBound::inclusive),
+                expires_before.map(|t| PrefixBound::exclusive(t.nanos())),
+                Order::Ascending,
+            )
+            .filter(move |res| match (res, &last) {
+                (Ok((pk, escrow)), Some((expires_at, last))) => escrow.expires_at != *expires_at || pk > last,
+                _ => true,
+            })
+            .map(|res| res.map(|item| item.1)))
+    } else {
+        Box::new(index
+            .range(deps.storage, start, None, Order::Ascending)
+            .map(|res| res.map(|item| item.1)))
+    };
+
+    let (escrows, last) = scan_page(candidates, limit, |escrow| {
+        filter.collection.as_ref().is_none_or(|c| &escrow.collection == c)
+            && filter.source.as_ref().is_none_or(|s| &escrow.source == s)
+            && filter.recipient.as_ref().is_none_or(|r| &escrow.recipient == r)
+            && (!price_filter || escrow.listed_price().is_some_and(|price| {
+                price.denom == denom
+                    && filter.min_price.is_none_or(|min| price.amount >= min)
+                    && filter.max_price.is_none_or(|max| price.amount <= max)
+            }))
+            && expires_after.is_none_or(|t| escrow.expires_at >= t)
+            && expires_before.is_none_or(|t| escrow.expires_at < t)
+    })?;
+
+    // resume after the last walked escrow, whether it matched or not
+    let next_cursor = last
+        .map(|last| to_binary(&SearchCursor {
+            collection: last.collection.clone(),
+            token_id: last.token_id.clone(),
+            price: last.listed_price().map(|price| price.amount).unwrap_or_default(),
+            expires_at: last.expires_at,
+        }))
+        .transpose()?;
+
+    let escrows = escrows
+        .into_iter()
+        .map(|escrow| EscrowSearchResult {
+            is_expired: escrow.is_expired(&env.block),
+            seconds_remaining: escrow.expires_at.seconds().saturating_sub(now.seconds()),
+            metadata: match include_metadata {
+                Some(true) => nft_metadata(deps, &escrow),
+                _ => None,
+            },
+            escrow,
+        })
+        .collect();
+
+    Ok(SearchEscrowsResponse { escrows, next_cursor })
+}
+
+pub fn query_simulate_approve(
+    deps: Deps,
+    env: Env,
+    collection: String,
+    token_id: String,
+    sender: String,
+) -> StdResult<SimulateApproveResponse> {
+    let escrow = match load_escrow(deps.storage, &collection, &token_id) {
+        Ok(escrow) => escrow,
+        Err(err) => return Ok(SimulateApproveResponse { required: vec![], error: Some(err.to_string()) }),
+    };
+
+    let required = required_funds(&env.block, &escrow);
+    let error = approve_check(&env.block, &escrow, &sender, &required)
+        .err()
+        .map(|err| err.to_string());
+
+    Ok(SimulateApproveResponse { required, error })
+}
+
+pub fn query_can_withdraw(
+    deps: Deps,
+    env: Env,
+    collection: String,
+    token_id: String,
+    sender: String,
+) -> StdResult<EligibilityResponse> {
+    let result = load_escrow(deps.storage, &collection, &token_id)
+        .and_then(|escrow| withdraw_check(deps.storage, &env.block, &escrow, &sender));
+
+    Ok(result.into())
+}
+
+pub fn query_can_cancel(
+    deps: Deps,
+    env: Env,
+    collection: String,
+    token_id: String,
+    sender: String,
+) -> StdResult<EligibilityResponse> {
+    let state = CONFIG.load(deps.storage)?;
+    let result = load_escrow(deps.storage, &collection, &token_id)
+        .and_then(|escrow| cancel_check(deps.storage, &state, &env.block, &escrow, &sender));
+
+    Ok(result.into())
+}
+
+pub fn query_operators(
+    deps: Deps,
+    env: Env,
+    source: String,
+    include_expired: bool,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<OperatorsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT) as usize;
+
+    let source = deps.api.addr_validate(&source)?;
+    let start_after = start_after.map(|addr| deps.api.addr_validate(&addr)).transpose()?;
+    let start = start_after.as_ref().map(Bound::exclusive);
+
+    let operators = OPERATORS
+        .prefix(&source)
+        .range(deps.storage, start, None, Order::Ascending)
+        .filter(|res| match res {
+            Ok((_, expires)) => include_expired || !expires.is_expired(&env.block),
+            Err(_) => true,
+        })
+        .take(limit)
+        .map(|res| res.map(|(spender, expires)| Approval { spender: spender.to_string(), expires }))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(OperatorsResponse { operators })
+}
+
+pub fn query_pubkey(deps: Deps, address: String) -> StdResult<PubkeyResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let pubkey = PUBKEYS.may_load(deps.storage, &address)?;
+    Ok(PubkeyResponse { pubkey })
+}
+
+pub fn query_nonce(deps: Deps, source: String, nonce: u64) -> StdResult<NonceResponse> {
+    let used = USED_NONCES.has(deps.storage, (&source, nonce));
+    Ok(NonceResponse { used })
+}
+
+pub fn query_preimage(deps: Deps, hash_lock: Binary) -> StdResult<PreimageResponse> {
+    let preimage = REVEALED_PREIMAGES.may_load(deps.storage, hash_lock.as_slice())?;
+    Ok(PreimageResponse { preimage })
+}
+
+pub fn query_current_price(deps: Deps, env: Env, collection: String, token_id: String) -> StdResult<CurrentPriceResponse> {
+    let escrow = escrows().load(deps.storage, escrow_key(&collection, &token_id))?;
+    Ok(CurrentPriceResponse { price: escrow.current_price(&env.block) })
+}
+
+pub fn query_bids(deps: Deps, escrow_id: u64, start_after: Option<u64>, limit: Option<u32>) -> StdResult<BidsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+
+    let bids = BIDS
+        .prefix(escrow_id)
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|res| res.map(|item| item.1))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(BidsResponse { bids })
+}
+
+pub fn query_sealed_bids(
+    deps: Deps,
+    escrow_id: u64,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<SealedBidsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT) as usize;
+    let start_after = start_after.map(|addr| deps.api.addr_validate(&addr)).transpose()?;
+    let start = start_after.as_ref().map(Bound::exclusive);
+
+    let bids = SEALED_BIDS
+        .prefix(escrow_id)
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|res| res.map(|item| item.1))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(SealedBidsResponse { bids })
+}
+
+pub fn query_offer(deps: Deps, offer_id: u64) -> StdResult<OfferResponse> {
+    let offer = offers().may_load(deps.storage, offer_id)?;
+    Ok(OfferResponse { offer })
+}
+
+pub fn query_offers_by_bidder(
+    deps: Deps,
+    bidder: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<OffersResponse> {
+    let limit = limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+
+    let offers = offers()
+        .idx
+        .bidder
+        .prefix(bidder)
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|res| res.map(|item| item.1))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(OffersResponse { offers })
+}
+
+pub fn query_offers_by_token(
+    deps: Deps,
+    collection: String,
+    token_id: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<OffersResponse> {
+    let limit = limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+
+    let offers = offers()
+        .idx
+        .token
+        .prefix((collection, token_id))
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|res| res.map(|item| item.1))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(OffersResponse { offers })
+}
+
+pub fn query_collection_offer(deps: Deps, offer_id: u64) -> StdResult<CollectionOfferResponse> {
+    let offer = collection_offers().may_load(deps.storage, offer_id)?;
+    Ok(CollectionOfferResponse { offer })
+}
+
+pub fn query_collection_offers(
+    deps: Deps,
+    env: Env,
+    collection: String,
+    denom: Option<String>,
+    start_after: Option<CollectionOfferOffset>,
+    limit: Option<u32>,
+) -> StdResult<BestCollectionOffersResponse> {
+    let limit = limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT) as usize;
+    let denom = match denom {
+        Some(denom) => denom,
+        None => CONFIG.load(deps.storage)?.denom,
+    };
+
+    // the index stores inverted prices, so ascending is highest price first;
+    // resume from the cursor's price, skipping what was already returned there
+    let best = match &start_after {
+        Some(offset) => price_key(Uint128::MAX - offset.price),
+        None => price_key(Uint128::zero()),
+    };
+
+    let index = collection_offers();
+    let entries = index
+        .idx
+        .collection_price
+        .prefix_range_raw(
+            deps.storage,
+            Some(PrefixBound::inclusive((collection.clone(), denom.clone(), best))),
+            Some(PrefixBound::inclusive((collection, denom, price_key(Uint128::MAX)))),
+            Order::Ascending,
+        )
+        .map(|res| res.map(|item| item.1))
+        .filter(|res| match (res, &start_after) {
+            (Ok(offer), Some(offset)) => offer.price.amount != offset.price || offer.id > offset.offer_id,
+            _ => true,
+        });
+    let (offers, last) = scan_page(entries, limit, |offer| !offer.is_expired(&env.block))?;
+    let next_start_after = last.map(|offer| CollectionOfferOffset { price: offer.price.amount, offer_id: offer.id });
+
+    Ok(BestCollectionOffersResponse { offers, next_start_after })
+}
+
+pub fn query_collection_offers_by_bidder(
+    deps: Deps,
+    bidder: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<CollectionOffersResponse> {
+    let limit = limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+
+    let offers = collection_offers()
+        .idx
+        .bidder
+        .prefix(bidder)
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|res| res.map(|item| item.1))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(CollectionOffersResponse { offers })
+}
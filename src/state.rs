@@ -1,9 +1,45 @@
-use cosmwasm_std::{Uint128, Timestamp, BlockInfo};
+use cosmwasm_std::{Addr, Binary, Coin, Uint128, Timestamp, BlockInfo};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use cw_storage_plus::{Item, MultiIndex, IndexList, Index, IndexedMap};
+use cw_storage_plus::{Item, Map, MultiIndex, IndexList, Index, IndexedMap};
+use cw_controllers::Hooks;
+use cw721::Expiration;
+use sha2::{Digest, Sha256};
 
 pub const CONFIG: Item<State> = Item::new("config_state");
+/// Last escrow id handed out, used to give every escrow a stable identifier
+pub const ESCROW_SEQ: Item<u64> = Item::new("escrow_seq");
+/// Contracts notified on escrow lifecycle events, managed by the admin
+pub const HOOKS: Hooks = Hooks::new("escrow_hooks");
+/// Trading aggregates per collection
+pub const COLLECTION_STATS: Map<&str, TradingStats> = Map::new("collection_stats");
+/// Trading aggregates over all collections
+pub const CONTRACT_STATS: Item<TradingStats> = Item::new("contract_stats");
+/// Number of escrows per source
+pub const SOURCE_COUNTS: Map<&str, u32> = Map::new("source_counts");
+/// Number of escrows per recipient
+pub const RECIPIENT_COUNTS: Map<&str, u32> = Map::new("recipient_counts");
+/// Operators a source approved to manage its escrows: (source, operator) -> expiry
+pub const OPERATORS: Map<(&Addr, &Addr), Expiration> = Map::new("operators");
+/// Escrows paying out to a payee: (payee, collection, token_id)
+pub const PAYEE_ESCROWS: Map<(&str, &str, &str), ()> = Map::new("payee_escrows");
+/// secp256k1 public keys sellers sign their off-chain offers with
+pub const PUBKEYS: Map<&Addr, Binary> = Map::new("pubkeys");
+/// Nonces of signed offers already fulfilled: (source, nonce)
+pub const USED_NONCES: Map<(&str, u64), ()> = Map::new("used_nonces");
+/// Preimages revealed by HTLC claims, by hash lock
+pub const REVEALED_PREIMAGES: Map<&[u8], Binary> = Map::new("revealed_preimages");
+/// English auction bids: (escrow id, bid id). Every bid beats the previous
+/// one, so the last bid of an escrow is the leading one.
+pub const BIDS: Map<(u64, u64), Bid> = Map::new("bids");
+/// Sealed bids: (escrow id, bidder)
+pub const SEALED_BIDS: Map<(u64, &Addr), SealedBid> = Map::new("sealed_bids");
+/// Last offer id handed out, shared by token and collection offers
+pub const OFFER_SEQ: Item<u64> = Item::new("offer_seq");
+/// Settlements waiting for their NFT transfer to be verified in `reply`, by
+/// escrow id, so settlements nested in a delivery callback or hook do not
+/// clash
+pub const PENDING_SETTLEMENTS: Map<u64, Settlement> = Map::new("pending_settlements");
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct State {
@@ -22,26 +58,389 @@ pub trait Order {
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
 pub struct Escrow {
+    pub id: u64,
     pub source: String,
     pub recipient: String,
-    pub price: Uint128,
+    /// Coins the buyer has to pay, at most one per denom. The first coin is
+    /// the one the escrow is listed under in the price index.
+    pub price: Vec<Coin>,
     pub expires_at: Timestamp,
     pub collection: String,
-    pub token_id: String
+    pub token_id: String,
+    /// Address the recipient authorized to pay on its behalf
+    #[serde(default)]
+    pub payer: Option<String>,
+    /// Where the NFT goes when the authorized payer approves, set by the
+    /// recipient along with the payer. Defaults to the recipient.
+    #[serde(default)]
+    pub payer_deliver_to: Option<String>,
+    #[serde(default)]
+    pub payer_deliver_msg: Option<Binary>,
+    /// Split of the proceeds, the source receives everything when empty
+    #[serde(default)]
+    pub payees: Vec<Payee>,
+    #[serde(default)]
+    pub kind: EscrowKind,
+    /// Contract query that has to answer `true` before the escrow settles
+    #[serde(default)]
+    pub condition: Option<Condition>,
+    /// Payment locked by `Approve` while the condition did not hold yet,
+    /// the escrow's `price` is what was paid
+    #[serde(default)]
+    pub payment: Option<LockedPayment>,
+}
+
+/// Smart query against `contract` with the raw `msg`, expected to return a bool
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct Condition {
+    pub contract: String,
+    pub msg: Binary,
+}
+
+/// What `Release` needs to settle a paid escrow
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct LockedPayment {
+    pub payer: String,
+    pub deliver_to: String,
+    pub deliver_msg: Option<Binary>,
+}
+
+/// How an escrow is settled
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum EscrowKind {
+    /// Paid for with `Approve`
+    #[default]
+    Standard,
+    /// Hash-time-locked: claimed with the preimage of the SHA-256
+    /// `hash_lock` before expiry, for atomic swaps with other chains
+    Htlc { hash_lock: Binary },
+    /// Descending price: starts at the escrow's single-coin `price` at
+    /// `start_time` and falls to `end_price` at expiry
+    DutchAuction {
+        end_price: Uint128,
+        start_time: Timestamp,
+        curve: DecayCurve,
+    },
+    /// Ascending bids, opening at the escrow's single-coin `price`. Each
+    /// bid must beat the leading one by `min_increment`. A bid within
+    /// `extension` seconds of expiry pushes the expiry to `extension`
+    /// seconds from then. Below `reserve` the NFT goes back unsold.
+    EnglishAuction {
+        reserve: Uint128,
+        min_increment: Uint128,
+        extension: u64,
+    },
+    /// Commit-reveal auction: bids are committed with a deposit until
+    /// `commit_end`, revealed until expiry and settled afterwards. The
+    /// escrow's single-coin `price` is the minimum bid.
+    SealedBidAuction {
+        commit_end: Timestamp,
+        pricing: SealedBidPricing,
+    },
+    /// Commissioned work paid for up front with `Approve`. The payment is
+    /// paid out to the source milestone by milestone, in the escrow's
+    /// single-coin `price` the amounts add up to, and the NFT is delivered
+    /// once every milestone is released. `arbiter` settles disputes.
+    Milestones {
+        arbiter: String,
+        milestones: Vec<Milestone>,
+    },
+}
+
+/// A tranche of a milestone escrow
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct Milestone {
+    pub amount: Uint128,
+    /// Hash of the agreed deliverable's description
+    pub description: Binary,
+    #[serde(default)]
+    pub status: MilestoneStatus,
+}
+
+/// Where a milestone stands
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum MilestoneStatus {
+    #[default]
+    Pending,
+    /// The source handed in `work`, usually a hash of the deliverable
+    Submitted { work: Binary },
+    /// Waiting for the arbiter
+    Disputed,
+    /// Paid out to the source
+    Released,
+}
+
+/// What the winner of a sealed-bid auction pays
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SealedBidPricing {
+    /// Its own bid
+    FirstPrice,
+    /// The second highest bid, or the minimum bid without one (Vickrey)
+    SecondPrice,
+}
+
+impl EscrowKind {
+    /// Auctions may leave the recipient empty to accept any buyer
+    pub fn is_auction(&self) -> bool {
+        matches!(
+            self,
+            EscrowKind::DutchAuction { .. } | EscrowKind::EnglishAuction { .. } | EscrowKind::SealedBidAuction { .. }
+        )
+    }
+
+    /// Whether the escrow is paid for with `Approve`
+    pub fn is_approvable(&self) -> bool {
+        matches!(self, EscrowKind::Standard | EscrowKind::DutchAuction { .. } | EscrowKind::Milestones { .. })
+    }
+}
+
+/// How a Dutch auction's price falls over time
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum DecayCurve {
+    /// Falls continuously
+    Linear,
+    /// Falls linearly, but only once every `interval` seconds
+    Stepped { interval: u64 },
 }
 
 
+/// Share of the seller proceeds, in basis points of the price
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct Payee {
+    pub address: String,
+    pub share_bps: u16,
+}
+
+/// Basis points the shares of all payees add up to
+pub const TOTAL_SHARE_BPS: u16 = 10_000;
+
+
 impl Order for Escrow {
     fn expires_at(&self) -> Timestamp {
         self.expires_at
     }
 }
 
+impl Escrow {
+    /// Coin the escrow is listed under in the price index. Only standard
+    /// escrows still up for sale are listed: Dutch auctions are left out as
+    /// their price keeps dropping after they are indexed, other auctions,
+    /// HTLCs and milestone commissions are not sold at it, and an escrow whose
+    /// payment is locked has already been bought.
+    pub fn listed_price(&self) -> Option<&Coin> {
+        match self.kind {
+            EscrowKind::Standard if self.payment.is_none() => self.price.first(),
+            _ => None,
+        }
+    }
+
+    /// Price a buyer pays at the given block
+    pub fn current_price(&self, block: &BlockInfo) -> Vec<Coin> {
+        match &self.kind {
+            EscrowKind::DutchAuction { end_price, start_time, curve } => {
+                let start = &self.price[0];
+                let duration = self.expires_at.seconds().saturating_sub(start_time.seconds());
+                let mut elapsed = block.time.seconds().saturating_sub(start_time.seconds()).min(duration);
+                if let DecayCurve::Stepped { interval } = curve {
+                    elapsed -= elapsed % interval;
+                }
+                let decay = (start.amount - *end_price).multiply_ratio(elapsed, duration.max(1));
+                vec![Coin { denom: start.denom.clone(), amount: start.amount - decay }]
+            }
+            _ => self.price.clone(),
+        }
+    }
+}
+
+/// A locked English auction bid
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct Bid {
+    /// Position in the auction's bid history, starting at 1
+    pub id: u64,
+    pub bidder: String,
+    pub amount: Coin,
+    pub placed_at: Timestamp,
+}
+
+/// A committed sealed bid
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct SealedBid {
+    pub bidder: String,
+    /// See `sealed_bid_commitment`
+    pub commitment: Binary,
+    /// Locked until settlement, must cover the revealed bid
+    pub deposit: Coin,
+    pub revealed: Option<Uint128>,
+}
+
+/// SHA-256 of the bidder address, the bid amount as 16 big-endian bytes
+/// and the salt. Binding the bidder keeps others from copying a commitment.
+pub fn sealed_bid_commitment(bidder: &str, amount: Uint128, salt: &[u8]) -> Binary {
+    let mut hasher = Sha256::new();
+    hasher.update(bidder.as_bytes());
+    hasher.update(amount.u128().to_be_bytes());
+    hasher.update(salt);
+    Binary::from(hasher.finalize().as_slice())
+}
+
+/// Aggregates kept up to date as escrows are created, removed and settled
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, JsonSchema, Debug)]
+pub struct TradingStats {
+    pub active_escrows: u64,
+    pub settlements: u64,
+    pub volume: Vec<DenomVolume>,
+    pub last_sale: Vec<Coin>,
+}
+
+/// Settled volume in a single denom
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct DenomVolume {
+    pub denom: String,
+    pub amount: Uint128,
+    pub settlements: u64,
+    pub all_time_high: Uint128,
+}
+
+impl TradingStats {
+    pub fn record_sale(&mut self, price: &[Coin]) {
+        self.settlements += 1;
+        self.last_sale = price.to_vec();
+
+        for coin in price {
+            match self.volume.iter_mut().find(|v| v.denom == coin.denom) {
+                Some(volume) => {
+                    volume.amount += coin.amount;
+                    volume.settlements += 1;
+                    volume.all_time_high = volume.all_time_high.max(coin.amount);
+                }
+                None => self.volume.push(DenomVolume {
+                    denom: coin.denom.clone(),
+                    amount: coin.amount,
+                    settlements: 1,
+                    all_time_high: coin.amount,
+                }),
+            }
+        }
+    }
+}
+
+/// An escrow whose NFT has been sent to the buyer but not yet verified
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct Settlement {
+    pub escrow: Escrow,
+    /// Address expected to own the NFT once the transfer went through
+    pub buyer: String,
+    /// Delivered with `SendNft`: the receiving contract may pass the NFT on
+    /// in its `ReceiveNft` handler, so only this contract giving it up is
+    /// verified
+    #[serde(default)]
+    pub sent: bool,
+}
+
+/// Funded buyer offer for a specific NFT, accepted by its owner sending the
+/// NFT with `ReceiveNftMsg::AcceptOffer`
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct Offer {
+    pub id: u64,
+    pub bidder: String,
+    pub collection: String,
+    pub token_id: String,
+    /// Coins locked with the offer
+    pub price: Vec<Coin>,
+    pub expires_at: Timestamp,
+}
+
+impl Order for Offer {
+    fn expires_at(&self) -> Timestamp {
+        self.expires_at
+    }
+}
+
+/// Defines indices for accessing Offers
+pub struct OfferIndicies<'a> {
+    pub bidder: MultiIndex<'a, String, Offer, u64>,
+    /// (collection, token_id)
+    pub token: MultiIndex<'a, (String, String), Offer, u64>,
+}
+
+impl<'a> IndexList<Offer> for OfferIndicies<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Offer>> + '_> {
+        let v: Vec<&dyn Index<Offer>> = vec![&self.bidder, &self.token];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Offers by id
+pub fn offers<'a>() -> IndexedMap<'a, u64, Offer, OfferIndicies<'a>> {
+    let indexes = OfferIndicies {
+        bidder: MultiIndex::new(|d: &Offer| d.bidder.clone(), "Offers", "Offers__bidder"),
+        token: MultiIndex::new(|d: &Offer| (d.collection.clone(), d.token_id.clone()), "Offers", "Offers__token"),
+    };
+    IndexedMap::new("Offers", indexes)
+}
+
+/// Funded offer for any NFT of a collection, filled one unit at a time by
+/// holders sending an NFT with `ReceiveNftMsg::FillCollectionOffer`
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct CollectionOffer {
+    pub id: u64,
+    pub bidder: String,
+    pub collection: String,
+    /// Paid per NFT
+    pub price: Coin,
+    /// Units still wanted, `price` is locked for each
+    pub quantity: u32,
+    pub expires_at: Timestamp,
+}
+
+impl Order for CollectionOffer {
+    fn expires_at(&self) -> Timestamp {
+        self.expires_at
+    }
+}
+
+/// Defines indices for accessing CollectionOffers
+pub struct CollectionOfferIndicies<'a> {
+    pub bidder: MultiIndex<'a, String, CollectionOffer, u64>,
+    /// (collection, denom, inverted price), so a collection's offers in a
+    /// denom iterate highest price first, older offers first on a tie
+    pub collection_price: MultiIndex<'a, (String, String, Vec<u8>), CollectionOffer, u64>,
+}
+
+impl<'a> IndexList<CollectionOffer> for CollectionOfferIndicies<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<CollectionOffer>> + '_> {
+        let v: Vec<&dyn Index<CollectionOffer>> = vec![&self.bidder, &self.collection_price];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Collection offers by id
+pub fn collection_offers<'a>() -> IndexedMap<'a, u64, CollectionOffer, CollectionOfferIndicies<'a>> {
+    let indexes = CollectionOfferIndicies {
+        bidder: MultiIndex::new(|d: &CollectionOffer| d.bidder.clone(), "CollectionOffers", "CollectionOffers__bidder"),
+        collection_price: MultiIndex::new(
+            |d: &CollectionOffer| (d.collection.clone(), d.price.denom.clone(), price_key(Uint128::MAX - d.price.amount)),
+            "CollectionOffers",
+            "CollectionOffers__collection_price",
+        ),
+    };
+    IndexedMap::new("CollectionOffers", indexes)
+}
+
 /// Primary key for Escrows: (collection, token_id)
 pub type EscrowKey<'a> = (String, String);
 /// Convenience Escrow key constructor
-pub fn escrow_key<'a>(collection: &'a String, token_id: &'a String) -> EscrowKey<'a> {
-    (collection.clone(), token_id.clone())
+pub fn escrow_key<'a>(collection: &'a str, token_id: &'a str) -> EscrowKey<'a> {
+    (collection.to_string(), token_id.to_string())
+}
+
+/// Big-endian price bytes, so the index sorts numerically
+pub fn price_key(price: Uint128) -> Vec<u8> {
+    price.u128().to_be_bytes().to_vec()
 }
 
 /// Defines indices for accessing Escrows
@@ -49,11 +448,16 @@ pub struct EscrowIndicies<'a> {
     pub collection: MultiIndex<'a, String, Escrow, EscrowKey<'a>>,
     pub source: MultiIndex<'a, String, Escrow, EscrowKey<'a>>,
     pub recipient: MultiIndex<'a, String, Escrow, EscrowKey<'a>>,
+    /// Expiration time in nanoseconds, so escrows iterate in the order they expire
+    pub expires_at: MultiIndex<'a, u64, Escrow, EscrowKey<'a>>,
+    /// (collection, denom, amount) of the listed price, so a collection's
+    /// escrows in a denom iterate cheapest first
+    pub collection_price: MultiIndex<'a, (String, String, Vec<u8>), Escrow, EscrowKey<'a>>,
 }
 
 impl<'a> IndexList<Escrow> for EscrowIndicies<'a> {
     fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Escrow>> + '_> {
-        let v: Vec<&dyn Index<Escrow>> = vec![&self.collection, &self.source, &self.recipient];
+        let v: Vec<&dyn Index<Escrow>> = vec![&self.collection, &self.source, &self.recipient, &self.expires_at, &self.collection_price];
         Box::new(v.into_iter())
     }
 }
@@ -63,6 +467,15 @@ pub fn escrows<'a>() -> IndexedMap<'a, EscrowKey<'a>, Escrow, EscrowIndicies<'a>
         collection: MultiIndex::new(|d: &Escrow| d.collection.clone(), "Escrows", "Escrows__collection"),
         source: MultiIndex::new(|d: &Escrow| d.source.clone(), "Escrows", "Escrows__source"),
         recipient: MultiIndex::new(|d: &Escrow| d.recipient.clone(), "Escrows", "Escrows__recipient"),
+        expires_at: MultiIndex::new(|d: &Escrow| d.expires_at.nanos(), "Escrows", "Escrows__expires_at"),
+        collection_price: MultiIndex::new(
+            |d: &Escrow| match d.listed_price() {
+                Some(price) => (d.collection.clone(), price.denom.clone(), price_key(price.amount)),
+                None => (d.collection.clone(), String::new(), price_key(Uint128::zero())),
+            },
+            "Escrows",
+            "Escrows__collection_price",
+        ),
     };
     IndexedMap::new("Escrows", indexes)
 }
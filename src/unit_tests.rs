@@ -1,11 +1,21 @@
 #[cfg(test)]
-use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
-use cosmwasm_std::{ DepsMut, Timestamp, Uint128,to_binary, Env,  CosmosMsg, WasmMsg, Coin, BankMsg};
-use cw721::{Cw721ReceiveMsg,Cw721ExecuteMsg};
+use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage};
+use cosmwasm_std::{ attr, DepsMut, Timestamp, Uint128,to_binary, Env,  CosmosMsg, WasmMsg, Coin, BankMsg, SubMsg, Reply, SubMsgResult, SubMsgExecutionResponse,
+  Order, OwnedDeps, WasmQuery, SystemResult, ContractResult, Response, SystemError, Binary};
+use cw721::{Cw721ReceiveMsg,Cw721ExecuteMsg, Expiration, OwnerOfResponse};
 
-use crate::contract::{execute, instantiate};
-use crate::msg::{ExecuteMsg, InstantiateMsg,  CollectionOffset, EscrowInfoMsg};
-use crate::query::{query_state_info, query_escrows_by_source, query_escrows_by_recipient};
+use crate::contract::{execute, instantiate, reply, settle_reply_id, HOOK_REPLY_ID};
+use crate::error::ContractError;
+use crate::msg::{ExecuteMsg, InstantiateMsg,  CollectionOffset, EscrowInfoMsg, EscrowHookMsg, ExpirationOffset, PriceOffset, SortOrder,
+  EscrowFilter, EscrowStatus, ReceiveNftMsg, SignedOffer, CollectionOfferOffset};
+use crate::state::{State, PENDING_SETTLEMENTS, Condition, DecayCurve, EscrowKind, Milestone, MilestoneStatus, Payee, SealedBidPricing, sealed_bid_commitment};
+use crate::query::{query_state_info, query_escrows_by_source, query_escrows_by_recipient, query_escrow, query_escrows_by_expiration,
+  query_escrows_by_price, query_floor_price, query_search_escrows,
+  query_collection_stats, query_contract_stats, query_escrows_count,
+  query_escrows_count_by_source, query_escrows_count_by_recipient, query_simulate_approve, query_can_withdraw,
+  query_can_cancel, query_escrows, query_operators, query_escrows_by_payee, query_nonce, query_preimage,
+  query_current_price, query_bids, query_sealed_bids, query_offer, query_offers_by_bidder, query_offers_by_token,
+  query_collection_offer, query_collection_offers, query_collection_offers_by_bidder};
 
 
 fn setup_contract(deps: DepsMut){
@@ -19,6 +29,33 @@ fn setup_contract(deps: DepsMut){
 }
 
 
+/// Makes every cw721 `OwnerOf` query answer with the given owner
+fn mock_nft_owner(deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>, owner: &str) {
+  let owner = owner.to_string();
+  deps.querier.update_wasm(move |query| match query {
+    WasmQuery::Smart { .. } => SystemResult::Ok(ContractResult::Ok(
+      to_binary(&OwnerOfResponse { owner: owner.clone(), approvals: vec![] }).unwrap()
+    )),
+    _ => panic!("unexpected query"),
+  });
+}
+
+fn ujuno(amount: u128) -> Coin {
+  Coin { denom: "ujuno".to_string(), amount: Uint128::new(amount) }
+}
+
+/// Simulates the successful NFT transfer sub-message of the oldest pending settlement
+fn settle_reply(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+  let escrow_id = PENDING_SETTLEMENTS.keys(deps.storage, None, None, Order::Ascending).next().unwrap().unwrap();
+  settle_reply_for(deps, env, escrow_id)
+}
+
+/// Simulates the successful NFT transfer sub-message settling the given escrow
+fn settle_reply_for(deps: DepsMut, env: Env, escrow_id: u64) -> Result<Response, ContractError> {
+  reply(deps, env, Reply { id: settle_reply_id(escrow_id), result: SubMsgResult::Ok(SubMsgExecutionResponse { events: vec![], data: None }) })
+}
+
+
 fn send_nft(
   deps: DepsMut, 
   env: Env, 
@@ -30,17 +67,40 @@ fn send_nft(
 ){
   let sell_msg = EscrowInfoMsg{
     recipient,
-    price,
+    price: vec![ujuno(price.u128())],
     expiration: Timestamp::from_seconds(env.block.time.seconds() + 300),
+    payees: vec![],
+    kind: EscrowKind::Standard,
+    condition: None,
 };
 
+  send_escrow_msg(deps, env, collection, sender, token_id, &sell_msg).unwrap();
+}
+
+/// Deposits an NFT into the contract with an arbitrary escrow payload
+fn send_escrow_msg<T: serde::Serialize>(
+  deps: DepsMut, 
+  env: Env, 
+  collection: &str, 
+  sender: String, 
+  token_id: String,
+  sell_msg: &T
+) -> Result<Response, ContractError> {
   let info = mock_info(collection, &[]);
   let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg{
       sender,
       token_id,
-      msg:to_binary(&sell_msg).unwrap()
+      msg:to_binary(sell_msg).unwrap()
   });
-  execute(deps, env, info.clone(), msg).unwrap();
+  execute(deps, env, info, msg)
+}
+
+/// The cw721 transfer moving an escrowed NFT out to `recipient`
+fn transfer_nft(collection: &str, recipient: &str, token_id: &str) -> WasmMsg {
+  WasmMsg::Execute{ 
+    contract_addr: collection.to_string(), 
+    msg: to_binary(&Cw721ExecuteMsg::TransferNft { recipient: recipient.to_string(), token_id: token_id.to_string() }).unwrap(), 
+    funds: vec![] }
 }
 
 
@@ -56,7 +116,7 @@ fn init_contract() {
     let res = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
     assert_eq!(0, res.messages.len());
     let state = query_state_info(deps.as_ref()).unwrap();
-    assert_eq!(state.admin,"owner".to_string());
+    assert_eq!(state.admin,"admin".to_string());
 }
 
 #[test]
@@ -87,15 +147,19 @@ fn send_nft_to_escrow_contract() {
     Uint128::new(50)
   );
 
-  let escrows_by_source = query_escrows_by_source(deps.as_ref(), "source1".to_string(), None, Some(30)).unwrap();
+  let escrows_by_source = query_escrows_by_source(deps.as_ref(), "source1".to_string(), None, Some(30), None).unwrap();
   println!("{:?}", escrows_by_source);
 
   
-  let escrows_by_source = query_escrows_by_source(deps.as_ref(), "source1".to_string(), Some(CollectionOffset{ collection: "collection1".to_string(), token_id: "Test.1".to_string()}), Some(30)).unwrap();
+  let escrows_by_source = query_escrows_by_source(deps.as_ref(), "source1".to_string(), Some(CollectionOffset{ collection: "collection1".to_string(), token_id: "Test.1".to_string()}), Some(30), None).unwrap();
   println!("{:?}", escrows_by_source);
 
-  let escrows_by_recipient = query_escrows_by_recipient(deps.as_ref(), "receiver1".to_string(), None, Some(30)).unwrap();
-  println!("{:?}", escrows_by_recipient)
+  let escrows_by_recipient = query_escrows_by_recipient(deps.as_ref(), "receiver1".to_string(), None, Some(30), None).unwrap();
+  println!("{:?}", escrows_by_recipient);
+
+  assert_eq!(query_escrows_count_by_source(deps.as_ref(), "source1".to_string()).unwrap().count, 2);
+  assert_eq!(query_escrows_count_by_recipient(deps.as_ref(), "receiver1".to_string()).unwrap().count, 1);
+  assert_eq!(query_escrows_count_by_recipient(deps.as_ref(), "nobody".to_string()).unwrap().count, 0);
 
 }
 
@@ -129,7 +193,7 @@ fn approve() {
   );
 
   let info = mock_info("receiver1", &[Coin{denom:"ujuno".to_string(), amount: Uint128::new(50) }]);
-  let msg = ExecuteMsg::Approve { collection: "collection1".to_string(), token_id: "Test.1".to_string() };
+  let msg = ExecuteMsg::Approve { collection: "collection1".to_string(), token_id: "Test.1".to_string(), deliver_to: None, deliver_msg: None };
   let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
   // assert_eq!(res.messages.len(),2);
 
@@ -143,9 +207,1508 @@ fn approve() {
       msg: to_binary(&Cw721ExecuteMsg::TransferNft { recipient: "receiver1".to_string(), token_id: "Test.1".to_string() }).unwrap(), 
       funds: vec![] })
   );
+  assert_eq!(res.messages[1].id, settle_reply_id(1));
 
-  let escrows_by_source = query_escrows_by_source(deps.as_ref(), "source1".to_string(), None, Some(30)).unwrap();
+  mock_nft_owner(&mut deps, "receiver1");
+  let res = settle_reply(deps.as_mut(), env.clone()).unwrap();
+
+  assert_eq!(res.events.len(), 1);
+  assert_eq!(res.events[0].ty, "escrow_approve");
+  assert_eq!(res.events[0].attributes, vec![
+    attr("id", "1"),
+    attr("collection", "collection1"),
+    attr("token_id", "Test.1"),
+    attr("source", "source1"),
+    attr("recipient", "receiver1"),
+    attr("price", "50ujuno"),
+    attr("denom", "ujuno"),
+    attr("expires_at", (env.block.time.seconds() + 300).to_string()),
+    attr("buyer", "receiver1"),
+  ]);
+
+  let escrows_by_source = query_escrows_by_source(deps.as_ref(), "source1".to_string(), None, Some(30), None).unwrap();
   println!("{:?}", escrows_by_source);
   
 }
 
+
+
+#[test]
+fn escrow_hooks() {
+  let mut deps = mock_dependencies();
+  let env = mock_env();
+
+  //init contract
+  setup_contract(deps.as_mut());
+
+  //only the admin can register hooks
+  let msg = ExecuteMsg::AddHook { addr: "rewards".to_string() };
+  let err = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), msg.clone()).unwrap_err();
+  assert!(matches!(err, ContractError::Unauthorized {  }));
+  execute(deps.as_mut(), env.clone(), mock_info("admin", &[]), msg).unwrap();
+
+  send_nft(
+    deps.as_mut(), 
+    env.clone(), 
+    "collection1", 
+    "source1".to_string(), 
+    "Test.1".to_string(), 
+    "receiver1".to_string(), 
+    Uint128::new(50)
+  );
+  let escrow = query_escrow(deps.as_ref(), "collection1".to_string(), "Test.1".to_string()).unwrap().escrow.unwrap();
+
+  let info = mock_info("receiver1", &[Coin{denom:"ujuno".to_string(), amount: Uint128::new(50) }]);
+  let msg = ExecuteMsg::Approve { collection: "collection1".to_string(), token_id: "Test.1".to_string(), deliver_to: None, deliver_msg: None };
+  execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+  mock_nft_owner(&mut deps, "receiver1");
+  let res = settle_reply(deps.as_mut(), env.clone()).unwrap();
+
+  let hook_msg = EscrowHookMsg::EscrowSettled { escrow, buyer: "receiver1".to_string() };
+  assert_eq!(res.messages[0], SubMsg::reply_on_error(hook_msg.into_cosmos_msg("rewards").unwrap(), HOOK_REPLY_ID));
+
+  //a failing hook is swallowed by the reply handler
+  let res = reply(deps.as_mut(), env.clone(), Reply { id: HOOK_REPLY_ID, result: SubMsgResult::Err("boom".to_string()) }).unwrap();
+  assert_eq!(res.attributes, vec![attr("action", "hook_failed"), attr("error", "boom")]);
+  //a successful hook reply is a no-op rather than a panic
+  let res = reply(deps.as_mut(), env.clone(), Reply { id: HOOK_REPLY_ID, result: SubMsgResult::Ok(SubMsgExecutionResponse { events: vec![], data: None }) }).unwrap();
+  assert!(res.attributes.is_empty());
+
+  let msg = ExecuteMsg::RemoveHook { addr: "rewards".to_string() };
+  execute(deps.as_mut(), env, mock_info("admin", &[]), msg).unwrap();
+}
+
+
+#[test]
+fn approve_with_deliver_msg() {
+  let mut deps = mock_dependencies();
+  let env = mock_env();
+
+  //init contract
+  setup_contract(deps.as_mut());
+
+  send_nft(
+    deps.as_mut(), 
+    env.clone(), 
+    "collection1", 
+    "source1".to_string(), 
+    "Test.1".to_string(), 
+    "vault".to_string(), 
+    Uint128::new(50)
+  );
+
+  let deliver_msg = to_binary(&"deposit").unwrap();
+  let info = mock_info("vault", &[Coin{denom:"ujuno".to_string(), amount: Uint128::new(50) }]);
+  let msg = ExecuteMsg::Approve { collection: "collection1".to_string(), token_id: "Test.1".to_string(), deliver_to: None, deliver_msg: Some(deliver_msg.clone()) };
+  let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+  assert_eq!(res.messages[1].msg, 
+    CosmosMsg::Wasm(WasmMsg::Execute{ 
+      contract_addr: "collection1".to_string(), 
+      msg: to_binary(&Cw721ExecuteMsg::SendNft { contract: "vault".to_string(), token_id: "Test.1".to_string(), msg: deliver_msg }).unwrap(), 
+      funds: vec![] })
+  );
+
+  //the NFT must have left this contract
+  mock_nft_owner(&mut deps, env.contract.address.as_str());
+  let err = settle_reply(deps.as_mut(), env.clone()).unwrap_err();
+  assert!(matches!(err, ContractError::SettlementNotVerified { .. }));
+
+  //but the vault may have forwarded it in its `ReceiveNft` handler
+  mock_nft_owner(&mut deps, "strategy");
+  settle_reply(deps.as_mut(), env).unwrap();
+}
+
+
+#[test]
+fn settlement_verifies_new_owner() {
+  let mut deps = mock_dependencies();
+  let env = mock_env();
+
+  //init contract
+  setup_contract(deps.as_mut());
+
+  send_nft(
+    deps.as_mut(), 
+    env.clone(), 
+    "collection1", 
+    "source1".to_string(), 
+    "Test.1".to_string(), 
+    "receiver1".to_string(), 
+    Uint128::new(50)
+  );
+
+  let info = mock_info("receiver1", &[Coin{denom:"ujuno".to_string(), amount: Uint128::new(50) }]);
+  let msg = ExecuteMsg::Approve { collection: "collection1".to_string(), token_id: "Test.1".to_string(), deliver_to: None, deliver_msg: None };
+  execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+  //the collection reports someone else as the owner after the transfer
+  mock_nft_owner(&mut deps, "thief");
+  let err = settle_reply(deps.as_mut(), env).unwrap_err();
+  assert!(matches!(err, ContractError::SettlementNotVerified { expected, owner } if expected == "receiver1" && owner == "thief"));
+}
+
+
+#[test]
+fn escrows_by_expiration() {
+  let mut deps = mock_dependencies();
+  let env = mock_env();
+  let now = env.block.time.seconds();
+
+  //init contract
+  setup_contract(deps.as_mut());
+
+  for (token_id, source, ttl) in [("Test.1", "source1", 500), ("Test.2", "source2", 100), ("Test.3", "source1", 300), ("Test.4", "source1", 300)] {
+    let sell_msg = EscrowInfoMsg{
+      recipient: "receiver1".to_string(),
+      price: vec![ujuno(50)],
+      expiration: Timestamp::from_seconds(now + ttl),
+      payees: vec![],
+      kind: EscrowKind::Standard,
+      condition: None,
+    };
+    send_escrow_msg(deps.as_mut(), env.clone(), "collection1", source.to_string(), token_id.to_string(), &sell_msg).unwrap();
+  }
+
+  let token_ids = |escrows: Vec<crate::state::Escrow>| escrows.into_iter().map(|e| e.token_id).collect::<Vec<_>>();
+
+  //soonest first
+  let res = query_escrows_by_expiration(deps.as_ref(), None, None, None, None, None, None, None).unwrap();
+  assert_eq!(token_ids(res.escrows), vec!["Test.2", "Test.3", "Test.4", "Test.1"]);
+
+  //window and source filter
+  let res = query_escrows_by_expiration(deps.as_ref(), Some(Timestamp::from_seconds(now + 200)), Some(Timestamp::from_seconds(now + 500)), Some("source1".to_string()), None, None, None, None).unwrap();
+  assert_eq!(token_ids(res.escrows), vec!["Test.3", "Test.4"]);
+
+  //cursor pagination inside the same expiration
+  let start_after = ExpirationOffset { expires_at: Timestamp::from_seconds(now + 300), collection: "collection1".to_string(), token_id: "Test.3".to_string() };
+  let res = query_escrows_by_expiration(deps.as_ref(), None, None, None, None, Some(start_after), Some(1), None).unwrap();
+  assert_eq!(token_ids(res.escrows), vec!["Test.4"]);
+
+  //the cursor follows storage order, where shorter collection names come first
+  for collection in ["bbb", "aaaa"] {
+    let sell_msg = EscrowInfoMsg{
+      recipient: "receiver1".to_string(),
+      price: vec![ujuno(50)],
+      expiration: Timestamp::from_seconds(now + 2000),
+      payees: vec![],
+      kind: EscrowKind::Standard,
+      condition: None,
+    };
+    send_escrow_msg(deps.as_mut(), env.clone(), collection, "source1".to_string(), "Test.5".to_string(), &sell_msg).unwrap();
+  }
+  let start = Some(Timestamp::from_seconds(now + 2000));
+  let res = query_escrows_by_expiration(deps.as_ref(), start, None, None, None, None, Some(1), None).unwrap();
+  assert_eq!(res.escrows[0].collection, "bbb");
+  let res = query_escrows_by_expiration(deps.as_ref(), start, None, None, None, res.next_start_after, Some(1), None).unwrap();
+  assert_eq!(res.escrows[0].collection, "aaaa");
+}
+
+
+#[test]
+fn escrows_by_price_and_floor() {
+  let mut deps = mock_dependencies();
+  let mut env = mock_env();
+  let now = env.block.time.seconds();
+
+  //init contract
+  setup_contract(deps.as_mut());
+
+  for (token_id, price, ttl) in [("Test.1", 300, 500), ("Test.2", 20, 100), ("Test.3", 100, 500), ("Test.4", 100, 500), ("Test.5", 1000, 500)] {
+    let sell_msg = EscrowInfoMsg{
+      recipient: "receiver1".to_string(),
+      price: vec![ujuno(price)],
+      expiration: Timestamp::from_seconds(now + ttl),
+      payees: vec![],
+      kind: EscrowKind::Standard,
+      condition: None,
+    };
+    send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "source1".to_string(), token_id.to_string(), &sell_msg).unwrap();
+  }
+
+  let token_ids = |escrows: Vec<crate::state::Escrow>| escrows.into_iter().map(|e| e.token_id).collect::<Vec<_>>();
+
+  let res = query_escrows_by_price(deps.as_ref(), env.clone(), "collection1".to_string(), None, None, Some(Uint128::new(300)), None, None, None, None).unwrap();
+  assert_eq!(token_ids(res.escrows), vec!["Test.2", "Test.3", "Test.4", "Test.1"]);
+
+  let start_after = PriceOffset { price: Uint128::new(100), token_id: "Test.4".to_string() };
+  let res = query_escrows_by_price(deps.as_ref(), env.clone(), "collection1".to_string(), None, None, None, Some(SortOrder::Descending), Some(start_after), None, None).unwrap();
+  assert_eq!(token_ids(res.escrows), vec!["Test.3", "Test.2"]);
+
+  let floor = query_floor_price(deps.as_ref(), env.clone(), "collection1".to_string(), None, None).unwrap();
+  assert_eq!(floor.price, Some(ujuno(20)));
+
+  //the cheapest escrow expired, so it no longer sets the floor
+  env.block.time = env.block.time.plus_seconds(200);
+  let floor = query_floor_price(deps.as_ref(), env.clone(), "collection1".to_string(), None, None).unwrap();
+  assert_eq!(floor.price, Some(ujuno(100)));
+
+  let floor = query_floor_price(deps.as_ref(), env, "collection2".to_string(), None, None).unwrap();
+  assert_eq!(floor.price, None);
+}
+
+
+#[test]
+fn search_escrows() {
+  let mut deps = mock_dependencies();
+  let mut env = mock_env();
+  let now = env.block.time.seconds();
+
+  //init contract
+  setup_contract(deps.as_mut());
+
+  for (collection, token_id, source, price, ttl) in [
+    ("collection1", "Test.1", "source1", 300, 500),
+    ("collection1", "Test.2", "source2", 20, 100),
+    ("collection1", "Test.3", "source1", 100, 500),
+    ("collection2", "Test.4", "source1", 100, 100),
+  ] {
+    let sell_msg = EscrowInfoMsg{
+      recipient: "receiver1".to_string(),
+      price: vec![ujuno(price)],
+      expiration: Timestamp::from_seconds(now + ttl),
+      payees: vec![],
+      kind: EscrowKind::Standard,
+      condition: None,
+    };
+    send_escrow_msg(deps.as_mut(), env.clone(), collection, source.to_string(), token_id.to_string(), &sell_msg).unwrap();
+  }
+  env.block.time = env.block.time.plus_seconds(200);
+
+  let token_ids = |res: &crate::msg::SearchEscrowsResponse| res.escrows.iter().map(|r| r.escrow.token_id.clone()).collect::<Vec<_>>();
+
+  //collection and price range, paginated with the opaque cursor
+  let filter = EscrowFilter { collection: Some("collection1".to_string()), min_price: Some(Uint128::new(50)), ..EscrowFilter::default() };
+  let res = query_search_escrows(deps.as_ref(), env.clone(), filter.clone(), None, Some(1), None).unwrap();
+  assert_eq!(token_ids(&res), vec!["Test.3"]);
+  assert_eq!(res.escrows[0].seconds_remaining, 300);
+  let res = query_search_escrows(deps.as_ref(), env.clone(), filter.clone(), res.next_cursor, Some(1), None).unwrap();
+  assert_eq!(token_ids(&res), vec!["Test.1"]);
+  let res = query_search_escrows(deps.as_ref(), env.clone(), filter, res.next_cursor, Some(1), None).unwrap();
+  assert!(res.escrows.is_empty());
+  assert_eq!(res.next_cursor, None);
+
+  //source combined with status
+  let filter = EscrowFilter { source: Some("source1".to_string()), status: Some(EscrowStatus::Expired), ..EscrowFilter::default() };
+  let res = query_search_escrows(deps.as_ref(), env.clone(), filter, None, None, None).unwrap();
+  assert_eq!(token_ids(&res), vec!["Test.4"]);
+  assert!(res.escrows[0].is_expired);
+  assert_eq!(res.escrows[0].seconds_remaining, 0);
+
+  //status alone walks the expiration index
+  let filter = EscrowFilter { status: Some(EscrowStatus::Active), ..EscrowFilter::default() };
+  let res = query_search_escrows(deps.as_ref(), env.clone(), filter, None, None, None).unwrap();
+  assert_eq!(token_ids(&res), vec!["Test.1", "Test.3"]);
+
+  //paging an expiry follows storage order, where shorter collection names come first
+  for collection in ["bbb", "aaaa"] {
+    let sell_msg = EscrowInfoMsg{
+      recipient: "receiver1".to_string(),
+      price: vec![ujuno(50)],
+      expiration: Timestamp::from_seconds(now + 2000),
+      payees: vec![],
+      kind: EscrowKind::Standard,
+      condition: None,
+    };
+    send_escrow_msg(deps.as_mut(), env.clone(), collection, "source1".to_string(), "Test.5".to_string(), &sell_msg).unwrap();
+  }
+  let filter = EscrowFilter { expires_after: Some(Timestamp::from_seconds(now + 2000)), ..EscrowFilter::default() };
+  let res = query_search_escrows(deps.as_ref(), env.clone(), filter.clone(), None, Some(1), None).unwrap();
+  assert_eq!(res.escrows[0].escrow.collection, "bbb");
+  let res = query_search_escrows(deps.as_ref(), env, filter, res.next_cursor, Some(1), None).unwrap();
+  assert_eq!(res.escrows[0].escrow.collection, "aaaa");
+}
+
+
+#[test]
+fn capped_scans() {
+  let mut deps = mock_dependencies();
+  let mut env = mock_env();
+  let now = env.block.time.seconds();
+
+  //init contract
+  setup_contract(deps.as_mut());
+
+  //a long run of cheap escrows that expire, then one that stays listed
+  for i in 0..150 {
+    let sell_msg = EscrowInfoMsg{
+      recipient: "receiver1".to_string(),
+      price: vec![ujuno(10)],
+      expiration: Timestamp::from_seconds(now + if i < 149 { 100 } else { 500 }),
+      payees: vec![],
+      kind: EscrowKind::Standard,
+      condition: None,
+    };
+    send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "source1".to_string(), format!("Test.{:03}", i), &sell_msg).unwrap();
+  }
+  env.block.time = env.block.time.plus_seconds(200);
+
+  //the floor walk stops at the cap and hands back where it stopped
+  let floor = query_floor_price(deps.as_ref(), env.clone(), "collection1".to_string(), None, None).unwrap();
+  assert_eq!(floor.escrow, None);
+  let start_after = floor.next_start_after.unwrap();
+  assert_eq!(start_after, PriceOffset { price: Uint128::new(10), token_id: "Test.099".to_string() });
+  let floor = query_floor_price(deps.as_ref(), env.clone(), "collection1".to_string(), None, Some(start_after)).unwrap();
+  assert_eq!(floor.escrow.unwrap().token_id, "Test.149");
+  assert_eq!(floor.next_start_after, None);
+
+  //same for the filtered listings
+  let res = query_escrows_by_price(deps.as_ref(), env.clone(), "collection1".to_string(), None, None, None, None, None, None, None).unwrap();
+  assert!(res.escrows.is_empty());
+  let res = query_escrows_by_price(deps.as_ref(), env.clone(), "collection1".to_string(), None, None, None, None, res.next_start_after, None, None).unwrap();
+  assert_eq!(res.escrows.len(), 1);
+  assert_eq!(res.next_start_after, None);
+
+  let filter = EscrowFilter { collection: Some("collection1".to_string()), status: Some(EscrowStatus::Active), ..EscrowFilter::default() };
+  let res = query_search_escrows(deps.as_ref(), env.clone(), filter.clone(), None, None, None).unwrap();
+  assert!(res.escrows.is_empty());
+  let res = query_search_escrows(deps.as_ref(), env, filter, res.next_cursor, None, None).unwrap();
+  assert_eq!(res.escrows.len(), 1);
+  assert_eq!(res.next_cursor, None);
+}
+
+
+#[test]
+fn trading_stats() {
+  let mut deps = mock_dependencies();
+  let env = mock_env();
+
+  //init contract
+  setup_contract(deps.as_mut());
+
+  for (token_id, price) in [("Test.1", 50), ("Test.2", 150), ("Test.3", 80)] {
+    send_nft(
+      deps.as_mut(), 
+      env.clone(), 
+      "collection1", 
+      "source1".to_string(), 
+      token_id.to_string(), 
+      "receiver1".to_string(), 
+      Uint128::new(price)
+    );
+  }
+  assert_eq!(query_escrows_count(deps.as_ref(), "collection1".to_string()).unwrap().count, 3);
+
+  mock_nft_owner(&mut deps, "receiver1");
+  for (token_id, price) in [("Test.1", 50), ("Test.2", 150)] {
+    let info = mock_info("receiver1", &[Coin{denom:"ujuno".to_string(), amount: Uint128::new(price) }]);
+    let msg = ExecuteMsg::Approve { collection: "collection1".to_string(), token_id: token_id.to_string(), deliver_to: None, deliver_msg: None };
+    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    settle_reply(deps.as_mut(), env.clone()).unwrap();
+  }
+
+  let stats = query_collection_stats(deps.as_ref(), "collection1".to_string()).unwrap();
+  assert_eq!(stats.active_escrows, 1);
+  assert_eq!(stats.settlements, 2);
+  assert_eq!(stats.volume, vec![Coin{denom:"ujuno".to_string(), amount: Uint128::new(200)}]);
+  assert_eq!(stats.average_price, vec![Coin{denom:"ujuno".to_string(), amount: Uint128::new(100)}]);
+  assert_eq!(stats.last_sale, vec![ujuno(150)]);
+  assert_eq!(stats.all_time_high, vec![Coin{denom:"ujuno".to_string(), amount: Uint128::new(150)}]);
+  assert_eq!(query_escrows_count(deps.as_ref(), "collection1".to_string()).unwrap().count, 1);
+  assert_eq!(query_escrows_count_by_source(deps.as_ref(), "source1".to_string()).unwrap().count, 1);
+  assert_eq!(query_escrows_count_by_recipient(deps.as_ref(), "receiver1".to_string()).unwrap().count, 1);
+
+  let stats = query_contract_stats(deps.as_ref()).unwrap();
+  assert_eq!(stats.active_escrows, 1);
+  assert_eq!(stats.settlements, 2);
+}
+
+
+#[test]
+fn simulate_and_eligibility() {
+  let mut deps = mock_dependencies();
+  let mut env = mock_env();
+
+  //init contract
+  setup_contract(deps.as_mut());
+
+  send_nft(
+    deps.as_mut(), 
+    env.clone(), 
+    "collection1", 
+    "source1".to_string(), 
+    "Test.1".to_string(), 
+    "receiver1".to_string(), 
+    Uint128::new(50)
+  );
+
+  let res = query_simulate_approve(deps.as_ref(), env.clone(), "collection1".to_string(), "Test.1".to_string(), "receiver1".to_string()).unwrap();
+  assert_eq!(res.required, vec![Coin{denom:"ujuno".to_string(), amount: Uint128::new(50)}]);
+  assert_eq!(res.error, None);
+
+  let res = query_simulate_approve(deps.as_ref(), env.clone(), "collection1".to_string(), "Test.1".to_string(), "stranger".to_string()).unwrap();
+  assert_eq!(res.error, Some(ContractError::Unauthorized {  }.to_string()));
+
+  let res = query_simulate_approve(deps.as_ref(), env.clone(), "collection1".to_string(), "Test.9".to_string(), "receiver1".to_string()).unwrap();
+  assert_eq!(res.error, Some(ContractError::NoEscrow {  }.to_string()));
+
+  let res = query_can_withdraw(deps.as_ref(), env.clone(), "collection1".to_string(), "Test.1".to_string(), "source1".to_string()).unwrap();
+  assert!(!res.allowed);
+  assert_eq!(res.error, Some(ContractError::EscrowNotExpired {  }.to_string()));
+
+  env.block.time = env.block.time.plus_seconds(300);
+  let res = query_can_withdraw(deps.as_ref(), env.clone(), "collection1".to_string(), "Test.1".to_string(), "source1".to_string()).unwrap();
+  assert!(res.allowed);
+
+  assert!(!query_can_cancel(deps.as_ref(), env.clone(), "collection1".to_string(), "Test.1".to_string(), "stranger".to_string()).unwrap().allowed);
+  assert!(query_can_cancel(deps.as_ref(), env.clone(), "collection1".to_string(), "Test.1".to_string(), "source1".to_string()).unwrap().allowed);
+  assert!(query_can_cancel(deps.as_ref(), env.clone(), "collection1".to_string(), "Test.1".to_string(), "admin".to_string()).unwrap().allowed);
+
+  //the admin cancels and the NFT goes back to its source
+  let msg = ExecuteMsg::Cancel { collection: "collection1".to_string(), token_id: "Test.1".to_string() };
+  let res = execute(deps.as_mut(), env, mock_info("admin", &[]), msg).unwrap();
+  assert_eq!(res.messages[0].msg, CosmosMsg::Wasm(transfer_nft("collection1", "source1", "Test.1")));
+  assert_eq!(query_escrow(deps.as_ref(), "collection1".to_string(), "Test.1".to_string()).unwrap().escrow, None);
+}
+
+
+#[test]
+fn escrows_with_metadata() {
+  let mut deps = mock_dependencies();
+  let env = mock_env();
+
+  //init contract
+  setup_contract(deps.as_mut());
+
+  for collection in ["collection1", "collection2"] {
+    send_nft(
+      deps.as_mut(), 
+      env.clone(), 
+      collection, 
+      "source1".to_string(), 
+      "Test.1".to_string(), 
+      "receiver1".to_string(), 
+      Uint128::new(50)
+    );
+  }
+
+  //collection1 answers NftInfo, collection2 is broken
+  let nft_info = Binary::from(br#"{"token_uri":"ipfs://test","extension":{"name":"Test"}}"#.to_vec());
+  let response = nft_info.clone();
+  deps.querier.update_wasm(move |query| match query {
+    WasmQuery::Smart { contract_addr, .. } if contract_addr == "collection1" => SystemResult::Ok(ContractResult::Ok(response.clone())),
+    _ => SystemResult::Err(SystemError::NoSuchContract { addr: "collection2".to_string() }),
+  });
+
+  let res = query_escrows(deps.as_ref(), "collection1".to_string(), None, None, None).unwrap();
+  assert!(res.metadata.is_empty());
+
+  let res = query_escrows_by_source(deps.as_ref(), "source1".to_string(), None, None, Some(true)).unwrap();
+  assert_eq!(res.escrows.len(), 2);
+  assert_eq!(res.metadata.len(), 2);
+  let metadata = res.metadata[0].clone().unwrap();
+  assert_eq!(metadata.token_uri, Some("ipfs://test".to_string()));
+  assert_eq!(metadata.nft_info, nft_info);
+  assert_eq!(res.metadata[1], None);
+}
+
+
+#[test]
+fn operators_manage_source_escrows() {
+  let mut deps = mock_dependencies();
+  let mut env = mock_env();
+
+  //init contract
+  setup_contract(deps.as_mut());
+
+  send_nft(
+    deps.as_mut(), 
+    env.clone(), 
+    "collection1", 
+    "source1".to_string(), 
+    "Test.1".to_string(), 
+    "receiver1".to_string(), 
+    Uint128::new(50)
+  );
+  send_nft(
+    deps.as_mut(), 
+    env.clone(), 
+    "collection1", 
+    "source1".to_string(), 
+    "Test.2".to_string(), 
+    "receiver1".to_string(), 
+    Uint128::new(50)
+  );
+
+  //strangers can not touch the escrow
+  let update = ExecuteMsg::UpdateEscrow {
+    collection: "collection1".to_string(),
+    token_id: "Test.1".to_string(),
+    recipient: Some("receiver2".to_string()),
+    price: Some(vec![ujuno(70)]),
+    expiration: None
+  };
+  let err = execute(deps.as_mut(), env.clone(), mock_info("custody", &[]), update.clone()).unwrap_err();
+  assert!(matches!(err, ContractError::Unauthorized {  }));
+
+  let msg = ExecuteMsg::ApproveAll { operator: "custody".to_string(), expires: Some(Expiration::AtTime(env.block.time.plus_seconds(1000))) };
+  execute(deps.as_mut(), env.clone(), mock_info("source1", &[]), msg).unwrap();
+
+  let operators = query_operators(deps.as_ref(), env.clone(), "source1".to_string(), false, None, None).unwrap();
+  assert_eq!(operators.operators.len(), 1);
+  assert_eq!(operators.operators[0].spender, "custody");
+
+  //the operator cancels one escrow of the source before expiry
+  let cancel = ExecuteMsg::Cancel { collection: "collection1".to_string(), token_id: "Test.2".to_string() };
+  let err = execute(deps.as_mut(), env.clone(), mock_info("stranger", &[]), cancel.clone()).unwrap_err();
+  assert!(matches!(err, ContractError::Unauthorized {  }));
+  let res = execute(deps.as_mut(), env.clone(), mock_info("custody", &[]), cancel).unwrap();
+  assert_eq!(res.messages[0].msg, CosmosMsg::Wasm(transfer_nft("collection1", "source1", "Test.2")));
+  assert_eq!(query_escrow(deps.as_ref(), "collection1".to_string(), "Test.2".to_string()).unwrap().escrow, None);
+
+  //it modifies and, once expired, withdraws back to the source
+  execute(deps.as_mut(), env.clone(), mock_info("custody", &[]), update).unwrap();
+  let escrow = query_escrow(deps.as_ref(), "collection1".to_string(), "Test.1".to_string()).unwrap().escrow.unwrap();
+  assert_eq!(escrow.recipient, "receiver2");
+  assert_eq!(escrow.price, vec![ujuno(70)]);
+  assert_eq!(query_escrows_count_by_recipient(deps.as_ref(), "receiver1".to_string()).unwrap().count, 0);
+  assert_eq!(query_escrows_count_by_recipient(deps.as_ref(), "receiver2".to_string()).unwrap().count, 1);
+
+  env.block.time = env.block.time.plus_seconds(300);
+  let msg = ExecuteMsg::WithdrawNft { collection: "collection1".to_string(), token_id: "Test.1".to_string() };
+  let res = execute(deps.as_mut(), env.clone(), mock_info("custody", &[]), msg).unwrap();
+  assert_eq!(res.messages[0].msg, CosmosMsg::Wasm(transfer_nft("collection1", "source1", "Test.1")));
+
+  let msg = ExecuteMsg::RevokeAll { operator: "custody".to_string() };
+  execute(deps.as_mut(), env.clone(), mock_info("source1", &[]), msg).unwrap();
+  let operators = query_operators(deps.as_ref(), env, "source1".to_string(), true, None, None).unwrap();
+  assert!(operators.operators.is_empty());
+}
+
+
+#[test]
+fn third_party_payer_and_delivery() {
+  let mut deps = mock_dependencies();
+  let env = mock_env();
+
+  //init contract
+  setup_contract(deps.as_mut());
+
+  send_nft(
+    deps.as_mut(), 
+    env.clone(), 
+    "collection1", 
+    "source1".to_string(), 
+    "Test.1".to_string(), 
+    "receiver1".to_string(), 
+    Uint128::new(50)
+  );
+
+  let approve = ExecuteMsg::Approve { collection: "collection1".to_string(), token_id: "Test.1".to_string(), deliver_to: None, deliver_msg: None };
+  let funds = [Coin{denom:"ujuno".to_string(), amount: Uint128::new(50) }];
+
+  //the treasury can not pay before the recipient authorizes it
+  let err = execute(deps.as_mut(), env.clone(), mock_info("treasury", &funds), approve.clone()).unwrap_err();
+  assert!(matches!(err, ContractError::Unauthorized {  }));
+
+  //the recipient names both the payer and the final owner
+  let msg = ExecuteMsg::AuthorizePayer {
+    collection: "collection1".to_string(),
+    token_id: "Test.1".to_string(),
+    payer: Some("treasury".to_string()),
+    deliver_to: Some("friend".to_string()),
+    deliver_msg: None
+  };
+  let err = execute(deps.as_mut(), env.clone(), mock_info("treasury", &[]), msg.clone()).unwrap_err();
+  assert!(matches!(err, ContractError::Unauthorized {  }));
+  let res = execute(deps.as_mut(), env.clone(), mock_info("receiver1", &[]), msg).unwrap();
+  assert_eq!(res.events[0].ty, "escrow_authorize_payer");
+  assert!(res.events[0].attributes.contains(&attr("recipient", "receiver1")));
+  assert!(res.events[0].attributes.contains(&attr("payer", "treasury")));
+  assert!(res.events[0].attributes.contains(&attr("deliver_to", "friend")));
+
+  //the treasury can not pick another owner itself
+  let redirect = ExecuteMsg::Approve { collection: "collection1".to_string(), token_id: "Test.1".to_string(), deliver_to: Some("treasury".to_string()), deliver_msg: None };
+  let err = execute(deps.as_mut(), env.clone(), mock_info("treasury", &funds), redirect).unwrap_err();
+  assert!(matches!(err, ContractError::Unauthorized {  }));
+
+  //treasury pays, the recipient authorized it and a friend receives the NFT
+  let res = execute(deps.as_mut(), env.clone(), mock_info("treasury", &funds), approve).unwrap();
+  assert_eq!(res.messages[1].msg, CosmosMsg::Wasm(transfer_nft("collection1", "friend", "Test.1")));
+
+  mock_nft_owner(&mut deps, "friend");
+  settle_reply(deps.as_mut(), env).unwrap();
+}
+
+
+#[test]
+fn split_proceeds_among_payees() {
+  let mut deps = mock_dependencies();
+  let env = mock_env();
+
+  //init contract
+  setup_contract(deps.as_mut());
+
+  let payee = |address: &str, share_bps| Payee { address: address.to_string(), share_bps };
+  let mut sell_msg = EscrowInfoMsg{
+    recipient: "receiver1".to_string(),
+    price: vec![ujuno(101)],
+    expiration: Timestamp::from_seconds(env.block.time.seconds() + 300),
+    payees: vec![payee("artist1", 5000), payee("artist2", 2500)],
+    kind: EscrowKind::Standard,
+    condition: None,
+  };
+  let err = send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "source1".to_string(), "Test.1".to_string(), &sell_msg).unwrap_err();
+  assert!(matches!(err, ContractError::InvalidPayeeShares { total: 7500 }));
+
+  sell_msg.payees = vec![payee("artist1", 5000), payee("artist2", 2500), payee("artist3", 2500)];
+  send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "source1".to_string(), "Test.1".to_string(), &sell_msg).unwrap();
+
+  let res = query_escrows_by_payee(deps.as_ref(), "artist2".to_string(), None, None, None).unwrap();
+  assert_eq!(res.escrows.len(), 1);
+
+  let info = mock_info("receiver1", &[Coin{denom:"ujuno".to_string(), amount: Uint128::new(101) }]);
+  let msg = ExecuteMsg::Approve { collection: "collection1".to_string(), token_id: "Test.1".to_string(), deliver_to: None, deliver_msg: None };
+  let res = execute(deps.as_mut(), env, info, msg).unwrap();
+
+  //the dust of the rounding goes to the first payee
+  let payment = |to: &str, amount| CosmosMsg::Bank(BankMsg::Send { to_address: to.to_string(), amount: vec![Coin{denom:"ujuno".to_string(), amount: Uint128::new(amount)}] });
+  assert_eq!(res.messages[0].msg, payment("artist1", 51));
+  assert_eq!(res.messages[1].msg, payment("artist2", 25));
+  assert_eq!(res.messages[2].msg, payment("artist3", 25));
+
+  let res = query_escrows_by_payee(deps.as_ref(), "artist2".to_string(), None, None, None).unwrap();
+  assert!(res.escrows.is_empty());
+}
+
+
+#[test]
+fn price_baskets() {
+  let mut deps = mock_dependencies();
+  let env = mock_env();
+
+  //init contract
+  setup_contract(deps.as_mut());
+
+  let uatom = |amount| Coin { denom: "uatom".to_string(), amount: Uint128::new(amount) };
+  let mut sell_msg = EscrowInfoMsg{
+    recipient: "receiver1".to_string(),
+    price: vec![ujuno(100), ujuno(5)],
+    expiration: Timestamp::from_seconds(env.block.time.seconds() + 300),
+    payees: vec![],
+    kind: EscrowKind::Standard,
+    condition: None,
+  };
+  let err = send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "source1".to_string(), "Test.1".to_string(), &sell_msg).unwrap_err();
+  assert!(matches!(err, ContractError::DuplicateDenom { .. }));
+
+  sell_msg.price = vec![ujuno(100), uatom(5)];
+  send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "source1".to_string(), "Test.1".to_string(), &sell_msg).unwrap();
+
+  //listed under its first coin, but not comparable for the floor
+  let res = query_escrows_by_price(deps.as_ref(), env.clone(), "collection1".to_string(), None, None, None, None, None, None, None).unwrap();
+  assert_eq!(res.escrows.len(), 1);
+  let floor = query_floor_price(deps.as_ref(), env.clone(), "collection1".to_string(), None, None).unwrap();
+  assert_eq!(floor.price, None);
+
+  let approve = |deps: DepsMut, funds: &[Coin]| {
+    let info = mock_info("receiver1", funds);
+    let msg = ExecuteMsg::Approve { collection: "collection1".to_string(), token_id: "Test.1".to_string(), deliver_to: None, deliver_msg: None };
+    execute(deps, env.clone(), info, msg)
+  };
+
+  let err = approve(deps.as_mut(), &[ujuno(100)]).unwrap_err();
+  assert!(matches!(err, ContractError::MissingCoin { denom } if denom == "uatom"));
+  let err = approve(deps.as_mut(), &[ujuno(100), uatom(4)]).unwrap_err();
+  assert!(matches!(err, ContractError::Underpayment { .. }));
+  let extra = Coin { denom: "uosmo".to_string(), amount: Uint128::new(1) };
+  let err = approve(deps.as_mut(), &[ujuno(100), uatom(5), extra]).unwrap_err();
+  assert!(matches!(err, ContractError::UnexpectedCoin { denom } if denom == "uosmo"));
+
+  //the whole basket goes to the source
+  let res = approve(deps.as_mut(), &[uatom(5), ujuno(100)]).unwrap();
+  assert_eq!(res.messages[0].msg,
+    CosmosMsg::Bank(BankMsg::Send { to_address: "source1".to_string(), amount: vec![ujuno(100), uatom(5)] })
+  );
+
+  mock_nft_owner(&mut deps, "receiver1");
+  let res = settle_reply(deps.as_mut(), env).unwrap();
+  assert!(res.events[0].attributes.contains(&attr("price", "100ujuno,5uatom")));
+  assert!(!res.events[0].attributes.iter().any(|attr| attr.key == "denom"));
+
+  let stats = query_contract_stats(deps.as_ref()).unwrap();
+  assert_eq!(stats.settlements, 1);
+  assert_eq!(stats.volume, vec![ujuno(100), uatom(5)]);
+  assert_eq!(stats.last_sale, vec![ujuno(100), uatom(5)]);
+}
+
+
+#[test]
+fn refund_overpayment() {
+  let mut deps = mock_dependencies();
+  let env = mock_env();
+
+  //init contract
+  setup_contract(deps.as_mut());
+
+  send_nft(
+    deps.as_mut(), 
+    env.clone(), 
+    "collection1", 
+    "source1".to_string(), 
+    "Test.1".to_string(), 
+    "receiver1".to_string(), 
+    Uint128::new(50)
+  );
+
+  let msg = ExecuteMsg::Approve { collection: "collection1".to_string(), token_id: "Test.1".to_string(), deliver_to: None, deliver_msg: None };
+  let err = execute(deps.as_mut(), env.clone(), mock_info("receiver1", &[ujuno(49)]), msg.clone()).unwrap_err();
+  assert_eq!(err.to_string(), "Underpayment: expected 50ujuno, received 49ujuno");
+
+  //the simulation still asks for the exact price
+  let res = query_simulate_approve(deps.as_ref(), env.clone(), "collection1".to_string(), "Test.1".to_string(), "receiver1".to_string()).unwrap();
+  assert_eq!(res.required, vec![ujuno(50)]);
+
+  let res = execute(deps.as_mut(), env, mock_info("receiver1", &[ujuno(60)]), msg).unwrap();
+  assert_eq!(res.messages.len(), 3);
+  assert_eq!(res.messages[0].msg,
+    CosmosMsg::Bank(BankMsg::Send { to_address: "source1".to_string(), amount: vec![ujuno(50)] })
+  );
+  assert_eq!(res.messages[2].msg,
+    CosmosMsg::Bank(BankMsg::Send { to_address: "receiver1".to_string(), amount: vec![ujuno(10)] })
+  );
+}
+
+
+#[test]
+fn fulfill_signed_offer() {
+  use k256::ecdsa::{signature::Signer, Signature, SigningKey, VerifyingKey};
+
+  let mut deps = mock_dependencies();
+  let env = mock_env();
+
+  //init contract
+  setup_contract(deps.as_mut());
+
+  let key = SigningKey::from_bytes(&[7u8; 32]).unwrap();
+  let sign = |offer: &SignedOffer| -> Binary {
+    let signature: Signature = key.sign(&cosmwasm_std::to_vec(offer).unwrap());
+    Binary::from(signature.as_ref())
+  };
+  let offer = SignedOffer {
+    contract: env.contract.address.to_string(),
+    chain_id: env.block.chain_id.clone(),
+    source: "source1".to_string(),
+    collection: "collection1".to_string(),
+    token_id: "Test.1".to_string(),
+    recipient: "receiver1".to_string(),
+    price: vec![ujuno(50)],
+    expires_at: Timestamp::from_seconds(env.block.time.seconds() + 300),
+    nonce: 1,
+  };
+  let fulfill = |offer: &SignedOffer, signature: Binary| ExecuteMsg::FulfillSignedOffer { offer: offer.clone(), signature };
+
+  let info = mock_info("receiver1", &[ujuno(50)]);
+  let err = execute(deps.as_mut(), env.clone(), info.clone(), fulfill(&offer, sign(&offer))).unwrap_err();
+  assert!(matches!(err, ContractError::NoPubkey { .. }));
+
+  let pubkey = VerifyingKey::from(&key).to_bytes();
+  let msg = ExecuteMsg::RegisterPubkey { pubkey: Binary::from(pubkey.as_slice()) };
+  execute(deps.as_mut(), env.clone(), mock_info("source1", &[]), msg).unwrap();
+
+  //an offer signed for another deployment is refused
+  let elsewhere = SignedOffer { chain_id: "other-chain".to_string(), ..offer.clone() };
+  let err = execute(deps.as_mut(), env.clone(), info.clone(), fulfill(&elsewhere, sign(&elsewhere))).unwrap_err();
+  assert!(matches!(err, ContractError::WrongOfferDomain { chain_id, .. } if chain_id == "other-chain"));
+  let elsewhere = SignedOffer { contract: "escrow2".to_string(), ..offer.clone() };
+  let err = execute(deps.as_mut(), env.clone(), info.clone(), fulfill(&elsewhere, sign(&elsewhere))).unwrap_err();
+  assert!(matches!(err, ContractError::WrongOfferDomain { contract, .. } if contract == "escrow2"));
+
+  //a tampered offer does not match the signature
+  let cheaper = SignedOffer { price: vec![ujuno(1)], ..offer.clone() };
+  let err = execute(deps.as_mut(), env.clone(), mock_info("receiver1", &[ujuno(1)]), fulfill(&cheaper, sign(&offer))).unwrap_err();
+  assert!(matches!(err, ContractError::InvalidSignature {}));
+
+  //the source signed, but has since sold the NFT elsewhere
+  mock_nft_owner(&mut deps, "someone");
+  let err = execute(deps.as_mut(), env.clone(), info.clone(), fulfill(&offer, sign(&offer))).unwrap_err();
+  assert!(matches!(err, ContractError::NotTokenOwner { address } if address == "source1"));
+
+  mock_nft_owner(&mut deps, "source1");
+  let err = execute(deps.as_mut(), env.clone(), mock_info("stranger", &[ujuno(50)]), fulfill(&offer, sign(&offer))).unwrap_err();
+  assert!(matches!(err, ContractError::Unauthorized {}));
+
+  let res = execute(deps.as_mut(), env.clone(), info.clone(), fulfill(&offer, sign(&offer))).unwrap();
+  assert_eq!(res.messages[0].msg,
+    CosmosMsg::Bank(BankMsg::Send { to_address: "source1".to_string(), amount: vec![ujuno(50)] })
+  );
+  assert_eq!(res.messages[1], SubMsg::reply_on_success(transfer_nft("collection1", "receiver1", "Test.1"), settle_reply_id(2)));
+  assert!(query_nonce(deps.as_ref(), "source1".to_string(), 1).unwrap().used);
+
+  mock_nft_owner(&mut deps, "receiver1");
+  settle_reply(deps.as_mut(), env.clone()).unwrap();
+
+  //the same offer can not be replayed
+  let err = execute(deps.as_mut(), env, info, fulfill(&offer, sign(&offer))).unwrap_err();
+  assert!(matches!(err, ContractError::NonceUsed { nonce: 1 }));
+}
+
+
+#[test]
+fn htlc_claim_and_refund() {
+  use sha2::{Digest, Sha256};
+
+  let mut deps = mock_dependencies();
+  let mut env = mock_env();
+
+  //init contract
+  setup_contract(deps.as_mut());
+
+  let preimage = Binary::from(b"secret".as_slice());
+  let hash_lock = Binary::from(Sha256::digest(preimage.as_slice()).as_slice());
+  let mut sell_msg = EscrowInfoMsg{
+    recipient: "receiver1".to_string(),
+    price: vec![],
+    expiration: Timestamp::from_seconds(env.block.time.seconds() + 300),
+    payees: vec![],
+    kind: EscrowKind::Htlc { hash_lock: Binary::from(b"short".as_slice()) },
+    condition: None,
+  };
+  let err = send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "source1".to_string(), "Test.1".to_string(), &sell_msg).unwrap_err();
+  assert!(matches!(err, ContractError::InvalidHashLock {}));
+
+  sell_msg.kind = EscrowKind::Htlc { hash_lock: hash_lock.clone() };
+  send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "source1".to_string(), "Test.1".to_string(), &sell_msg).unwrap();
+  send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "source1".to_string(), "Test.2".to_string(), &sell_msg).unwrap();
+
+  //only the preimage releases the NFT, and the source can not pull it early
+  let msg = ExecuteMsg::Approve { collection: "collection1".to_string(), token_id: "Test.1".to_string(), deliver_to: None, deliver_msg: None };
+  let err = execute(deps.as_mut(), env.clone(), mock_info("receiver1", &[]), msg).unwrap_err();
+  assert!(matches!(err, ContractError::WrongEscrowKind {}));
+  let msg = ExecuteMsg::Cancel { collection: "collection1".to_string(), token_id: "Test.1".to_string() };
+  let err = execute(deps.as_mut(), env.clone(), mock_info("source1", &[]), msg).unwrap_err();
+  assert!(matches!(err, ContractError::WrongEscrowKind {}));
+
+  let claim = |token_id: &str, preimage: &Binary| ExecuteMsg::ClaimHtlc { collection: "collection1".to_string(), token_id: token_id.to_string(), preimage: preimage.clone() };
+  let wrong = Binary::from(b"guess".as_slice());
+  let err = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), claim("Test.1", &wrong)).unwrap_err();
+  assert!(matches!(err, ContractError::InvalidPreimage {}));
+
+  //anyone holding the secret can claim, the NFT goes to the recipient
+  let res = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), claim("Test.1", &preimage)).unwrap();
+  assert_eq!(res.messages.len(), 1);
+  assert_eq!(res.messages[0], SubMsg::reply_on_success(transfer_nft("collection1", "receiver1", "Test.1"), settle_reply_id(1)));
+  assert_eq!(res.events[0].ty, "escrow_claim");
+  assert!(res.events[0].attributes.contains(&attr("preimage", preimage.to_base64())));
+  assert_eq!(query_preimage(deps.as_ref(), hash_lock).unwrap().preimage, Some(preimage.clone()));
+
+  mock_nft_owner(&mut deps, "receiver1");
+  settle_reply(deps.as_mut(), env.clone()).unwrap();
+
+  //after expiry the secret no longer works and the source takes the NFT back
+  env.block.time = env.block.time.plus_seconds(300);
+  let err = execute(deps.as_mut(), env.clone(), mock_info("receiver1", &[]), claim("Test.2", &preimage)).unwrap_err();
+  assert!(matches!(err, ContractError::EscrowExpired {}));
+  let msg = ExecuteMsg::WithdrawNft { collection: "collection1".to_string(), token_id: "Test.2".to_string() };
+  execute(deps.as_mut(), env, mock_info("source1", &[]), msg).unwrap();
+}
+
+
+#[test]
+fn dutch_auction() {
+  let mut deps = mock_dependencies();
+  let mut env = mock_env();
+
+  //init contract
+  setup_contract(deps.as_mut());
+
+  let auction = |end_price, curve| EscrowKind::DutchAuction { end_price: Uint128::new(end_price), start_time: env.block.time, curve };
+  let mut sell_msg = EscrowInfoMsg{
+    recipient: "".to_string(),
+    price: vec![ujuno(1000)],
+    expiration: Timestamp::from_seconds(env.block.time.seconds() + 300),
+    payees: vec![],
+    kind: auction(1000, DecayCurve::Linear),
+    condition: None,
+  };
+  let err = send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "source1".to_string(), "Test.1".to_string(), &sell_msg).unwrap_err();
+  assert!(matches!(err, ContractError::InvalidAuction { .. }));
+
+  sell_msg.kind = auction(100, DecayCurve::Linear);
+  send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "source1".to_string(), "Test.1".to_string(), &sell_msg).unwrap();
+  sell_msg.kind = auction(100, DecayCurve::Stepped { interval: 100 });
+  send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "source1".to_string(), "Test.2".to_string(), &sell_msg).unwrap();
+
+  //the start price would soon be stale, so the price queries skip them
+  let floor = query_floor_price(deps.as_ref(), env.clone(), "collection1".to_string(), None, None).unwrap();
+  assert_eq!(floor.escrow, None);
+  let res = query_escrows_by_price(deps.as_ref(), env.clone(), "collection1".to_string(), None, None, None, None, None, None, None).unwrap();
+  assert!(res.escrows.is_empty());
+
+  let price = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>, env: &Env, token_id: &str| {
+    query_current_price(deps.as_ref(), env.clone(), "collection1".to_string(), token_id.to_string()).unwrap().price
+  };
+  assert_eq!(price(&deps, &env, "Test.1"), vec![ujuno(1000)]);
+
+  env.block.time = env.block.time.plus_seconds(150);
+  assert_eq!(price(&deps, &env, "Test.1"), vec![ujuno(550)]);
+  assert_eq!(price(&deps, &env, "Test.2"), vec![ujuno(700)]);
+
+  let msg = ExecuteMsg::Approve { collection: "collection1".to_string(), token_id: "Test.1".to_string(), deliver_to: None, deliver_msg: None };
+  let err = execute(deps.as_mut(), env.clone(), mock_info("buyer1", &[ujuno(500)]), msg.clone()).unwrap_err();
+  assert!(matches!(err, ContractError::Underpayment { .. }));
+
+  //any buyer settles at the current price and gets the rest back
+  let res = execute(deps.as_mut(), env.clone(), mock_info("buyer1", &[ujuno(600)]), msg).unwrap();
+  assert_eq!(res.messages[0].msg,
+    CosmosMsg::Bank(BankMsg::Send { to_address: "source1".to_string(), amount: vec![ujuno(550)] })
+  );
+  assert_eq!(res.messages[1].msg, CosmosMsg::Wasm(transfer_nft("collection1", "buyer1", "Test.1")));
+  assert_eq!(res.messages[2].msg,
+    CosmosMsg::Bank(BankMsg::Send { to_address: "buyer1".to_string(), amount: vec![ujuno(50)] })
+  );
+
+  //the auction was open to anyone, the event names who bought it
+  mock_nft_owner(&mut deps, "buyer1");
+  let res = settle_reply(deps.as_mut(), env.clone()).unwrap();
+  assert!(res.events[0].attributes.contains(&attr("recipient", "")));
+  assert!(res.events[0].attributes.contains(&attr("buyer", "buyer1")));
+  let stats = query_collection_stats(deps.as_ref(), "collection1".to_string()).unwrap();
+  assert_eq!(stats.last_sale, vec![ujuno(550)]);
+
+  //the stepped curve only drops on each full interval
+  env.block.time = env.block.time.plus_seconds(149);
+  assert_eq!(price(&deps, &env, "Test.2"), vec![ujuno(400)]);
+}
+
+
+#[test]
+fn english_auction() {
+  let mut deps = mock_dependencies();
+  let mut env = mock_env();
+
+  //init contract
+  setup_contract(deps.as_mut());
+
+  let mut sell_msg = EscrowInfoMsg{
+    recipient: "".to_string(),
+    price: vec![ujuno(100)],
+    expiration: Timestamp::from_seconds(env.block.time.seconds() + 300),
+    payees: vec![],
+    kind: EscrowKind::EnglishAuction { reserve: Uint128::new(150), min_increment: Uint128::new(10), extension: 60 },
+    condition: None,
+  };
+  //the winning bidder gets the NFT, there is no recipient to name
+  let private = EscrowInfoMsg { recipient: "receiver1".to_string(), ..sell_msg.clone() };
+  let err = send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "source1".to_string(), "Test.1".to_string(), &private).unwrap_err();
+  assert!(matches!(err, ContractError::InvalidAuction { .. }));
+  send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "source1".to_string(), "Test.1".to_string(), &sell_msg).unwrap();
+
+  //the opening price is no buy-now price, so the price queries skip it
+  let floor = query_floor_price(deps.as_ref(), env.clone(), "collection1".to_string(), None, None).unwrap();
+  assert_eq!(floor.escrow, None);
+
+  let bid = |deps: DepsMut, env: &Env, bidder: &str, amount| {
+    let msg = ExecuteMsg::Bid { collection: "collection1".to_string(), token_id: "Test.1".to_string() };
+    execute(deps, env.clone(), mock_info(bidder, &[ujuno(amount)]), msg)
+  };
+  let settle = |deps: DepsMut, env: &Env, token_id: &str| {
+    let msg = ExecuteMsg::Settle { collection: "collection1".to_string(), token_id: token_id.to_string() };
+    execute(deps, env.clone(), mock_info("anyone", &[]), msg)
+  };
+  let refund = |to: &str, amount| CosmosMsg::Bank(BankMsg::Send { to_address: to.to_string(), amount: vec![ujuno(amount)] });
+
+  let err = bid(deps.as_mut(), &env, "source1", 100).unwrap_err();
+  assert!(matches!(err, ContractError::Unauthorized {}));
+  let err = bid(deps.as_mut(), &env, "bidder1", 90).unwrap_err();
+  assert!(matches!(err, ContractError::Underpayment { .. }));
+  let res = bid(deps.as_mut(), &env, "bidder1", 100).unwrap();
+  assert!(res.messages.is_empty());
+
+  //must beat the leading bid by the increment, the outbid bidder is refunded
+  let err = bid(deps.as_mut(), &env, "bidder2", 105).unwrap_err();
+  assert_eq!(err.to_string(), "Underpayment: expected 110ujuno, received 105ujuno");
+  let res = bid(deps.as_mut(), &env, "bidder2", 120).unwrap();
+  assert_eq!(res.messages[0].msg, refund("bidder1", 100));
+
+  let msg = ExecuteMsg::Cancel { collection: "collection1".to_string(), token_id: "Test.1".to_string() };
+  let err = execute(deps.as_mut(), env.clone(), mock_info("source1", &[]), msg).unwrap_err();
+  assert!(matches!(err, ContractError::AuctionHasBids {}));
+  let err = settle(deps.as_mut(), &env, "Test.1").unwrap_err();
+  assert!(matches!(err, ContractError::EscrowNotExpired {}));
+
+  //a bid in the last minute extends the auction
+  env.block.time = env.block.time.plus_seconds(270);
+  let res = bid(deps.as_mut(), &env, "bidder1", 130).unwrap();
+  assert_eq!(res.messages[0].msg, refund("bidder2", 120));
+  assert_eq!(res.events[0].ty, "escrow_bid");
+  assert!(res.events[0].attributes.contains(&attr("source", "source1")));
+  assert!(res.events[0].attributes.contains(&attr("amount", "130ujuno")));
+  assert!(res.events[0].attributes.contains(&attr("expires_at", env.block.time.plus_seconds(60).seconds().to_string())));
+  env.block.time = env.block.time.plus_seconds(30);
+  let err = settle(deps.as_mut(), &env, "Test.1").unwrap_err();
+  assert!(matches!(err, ContractError::EscrowNotExpired {}));
+
+  let escrow_id = query_escrow(deps.as_ref(), "collection1".to_string(), "Test.1".to_string()).unwrap().escrow.unwrap().id;
+  let bids = query_bids(deps.as_ref(), escrow_id, None, None).unwrap().bids;
+  assert_eq!(bids.iter().map(|bid| bid.amount.amount.u128()).collect::<Vec<_>>(), vec![100, 120, 130]);
+  let bids = query_bids(deps.as_ref(), escrow_id, Some(1), Some(1)).unwrap().bids;
+  assert_eq!(bids[0].bidder, "bidder2");
+
+  //below the reserve the NFT goes back and the leading bid is refunded
+  env.block.time = env.block.time.plus_seconds(30);
+  let res = settle(deps.as_mut(), &env, "Test.1").unwrap();
+  assert_eq!(res.events[0].ty, "escrow_unsold");
+  assert_eq!(res.messages[0].msg, CosmosMsg::Wasm(transfer_nft("collection1", "source1", "Test.1")));
+  assert_eq!(res.messages[1].msg, refund("bidder1", 130));
+
+  //relisted without a reserve, the leading bid wins once expired
+  sell_msg.expiration = Timestamp::from_seconds(env.block.time.seconds() + 300);
+  sell_msg.kind = EscrowKind::EnglishAuction { reserve: Uint128::zero(), min_increment: Uint128::new(10), extension: 60 };
+  send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "source1".to_string(), "Test.1".to_string(), &sell_msg).unwrap();
+  bid(deps.as_mut(), &env, "bidder2", 100).unwrap();
+  env.block.time = env.block.time.plus_seconds(300);
+  let res = settle(deps.as_mut(), &env, "Test.1").unwrap();
+  assert_eq!(res.messages[0].msg,
+    CosmosMsg::Bank(BankMsg::Send { to_address: "source1".to_string(), amount: vec![ujuno(100)] })
+  );
+  assert_eq!(res.messages[1].msg, CosmosMsg::Wasm(transfer_nft("collection1", "bidder2", "Test.1")));
+  mock_nft_owner(&mut deps, "bidder2");
+  settle_reply(deps.as_mut(), env).unwrap();
+}
+
+
+#[test]
+fn sealed_bid_auction() {
+  let mut deps = mock_dependencies();
+  let mut env = mock_env();
+
+  //init contract
+  setup_contract(deps.as_mut());
+
+  let sell_msg = EscrowInfoMsg{
+    recipient: "".to_string(),
+    price: vec![ujuno(100)],
+    expiration: Timestamp::from_seconds(env.block.time.seconds() + 200),
+    payees: vec![],
+    kind: EscrowKind::SealedBidAuction { commit_end: env.block.time.plus_seconds(100), pricing: SealedBidPricing::SecondPrice },
+    condition: None,
+  };
+  send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "source1".to_string(), "Test.1".to_string(), &sell_msg).unwrap();
+
+  let salt = |bidder: &str| Binary::from(format!("salt-{}", bidder).as_bytes());
+  let commit = |deps: DepsMut, env: &Env, bidder: &str, amount, deposit| {
+    let commitment = sealed_bid_commitment(bidder, Uint128::new(amount), &salt(bidder));
+    let msg = ExecuteMsg::CommitBid { collection: "collection1".to_string(), token_id: "Test.1".to_string(), commitment };
+    execute(deps, env.clone(), mock_info(bidder, &[ujuno(deposit)]), msg)
+  };
+  let reveal = |deps: DepsMut, env: &Env, bidder: &str, amount, salt: Binary| {
+    let msg = ExecuteMsg::RevealBid { collection: "collection1".to_string(), token_id: "Test.1".to_string(), amount: Uint128::new(amount), salt };
+    execute(deps, env.clone(), mock_info(bidder, &[]), msg)
+  };
+
+  //deposits hide the bids behind them
+  commit(deps.as_mut(), &env, "bidder1", 300, 500).unwrap();
+  commit(deps.as_mut(), &env, "bidder2", 200, 200).unwrap();
+  commit(deps.as_mut(), &env, "bidder3", 150, 150).unwrap();
+  let err = commit(deps.as_mut(), &env, "bidder2", 250, 250).unwrap_err();
+  assert!(matches!(err, ContractError::AlreadyCommitted {}));
+  let err = reveal(deps.as_mut(), &env, "bidder1", 300, salt("bidder1")).unwrap_err();
+  assert!(matches!(err, ContractError::AuctionPhase { .. }));
+
+  env.block.time = env.block.time.plus_seconds(100);
+  let err = commit(deps.as_mut(), &env, "bidder4", 400, 400).unwrap_err();
+  assert!(matches!(err, ContractError::AuctionPhase { .. }));
+  let err = reveal(deps.as_mut(), &env, "bidder1", 300, salt("bidder2")).unwrap_err();
+  assert!(matches!(err, ContractError::InvalidReveal {}));
+  reveal(deps.as_mut(), &env, "bidder1", 300, salt("bidder1")).unwrap();
+  reveal(deps.as_mut(), &env, "bidder2", 200, salt("bidder2")).unwrap();
+
+  let escrow_id = query_escrow(deps.as_ref(), "collection1".to_string(), "Test.1".to_string()).unwrap().escrow.unwrap().id;
+  let bids = query_sealed_bids(deps.as_ref(), escrow_id, None, None).unwrap().bids;
+  assert_eq!(bids.iter().map(|bid| bid.revealed).collect::<Vec<_>>(), vec![Some(Uint128::new(300)), Some(Uint128::new(200)), None]);
+
+  let settle = ExecuteMsg::Settle { collection: "collection1".to_string(), token_id: "Test.1".to_string() };
+  let err = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), settle.clone()).unwrap_err();
+  assert!(matches!(err, ContractError::EscrowNotExpired {}));
+
+  //the highest bid wins at the second price, every other deposit goes back
+  env.block.time = env.block.time.plus_seconds(100);
+  let res = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), settle).unwrap();
+  let payment = |to: &str, amount| CosmosMsg::Bank(BankMsg::Send { to_address: to.to_string(), amount: vec![ujuno(amount)] });
+  assert_eq!(res.messages[0].msg, payment("source1", 200));
+  assert_eq!(res.messages[1].msg, CosmosMsg::Wasm(transfer_nft("collection1", "bidder1", "Test.1")));
+  assert_eq!(res.messages[2].msg, payment("bidder1", 300));
+  assert_eq!(res.messages[3].msg, payment("bidder2", 200));
+  assert_eq!(res.messages[4].msg, payment("bidder3", 150));
+  assert!(query_sealed_bids(deps.as_ref(), escrow_id, None, None).unwrap().bids.is_empty());
+
+  mock_nft_owner(&mut deps, "bidder1");
+  settle_reply(deps.as_mut(), env).unwrap();
+}
+
+
+/// Answers the "oracle" condition contract with `answer` and every
+/// cw721 `OwnerOf` query with the given owner
+fn mock_oracle(deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>, answer: bool, owner: &str) {
+  let owner = owner.to_string();
+  deps.querier.update_wasm(move |query| match query {
+    WasmQuery::Smart { contract_addr, .. } if contract_addr == "oracle" => SystemResult::Ok(ContractResult::Ok(
+      to_binary(&answer).unwrap()
+    )),
+    WasmQuery::Smart { .. } => SystemResult::Ok(ContractResult::Ok(
+      to_binary(&OwnerOfResponse { owner: owner.clone(), approvals: vec![] }).unwrap()
+    )),
+    _ => panic!("unexpected query"),
+  });
+}
+
+#[test]
+fn conditional_release() {
+  let mut deps = mock_dependencies();
+  let mut env = mock_env();
+
+  //init contract
+  setup_contract(deps.as_mut());
+
+  let sell_msg = EscrowInfoMsg{
+    recipient: "receiver1".to_string(),
+    price: vec![ujuno(100)],
+    expiration: Timestamp::from_seconds(env.block.time.seconds() + 300),
+    payees: vec![],
+    kind: EscrowKind::Standard,
+    condition: Some(Condition { contract: "oracle".to_string(), msg: Binary::from(br#"{"delivered":{}}"#.to_vec()) }),
+  };
+  send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "source1".to_string(), "Test.1".to_string(), &sell_msg).unwrap();
+  send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "source1".to_string(), "Test.2".to_string(), &sell_msg).unwrap();
+
+  let approve = |token_id: &str| ExecuteMsg::Approve { collection: "collection1".to_string(), token_id: token_id.to_string(), deliver_to: None, deliver_msg: None };
+  let release = |token_id: &str| ExecuteMsg::Release { collection: "collection1".to_string(), token_id: token_id.to_string() };
+
+  //nothing to release before the payment
+  let err = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), release("Test.1")).unwrap_err();
+  assert!(matches!(err, ContractError::NotPaid {}));
+
+  //while the oracle says no, the payment is locked and only the surplus comes back
+  mock_oracle(&mut deps, false, "receiver1");
+  let res = execute(deps.as_mut(), env.clone(), mock_info("receiver1", &[ujuno(120)]), approve("Test.1")).unwrap();
+  assert_eq!(res.messages, vec![SubMsg::new(BankMsg::Send { to_address: "receiver1".to_string(), amount: vec![ujuno(20)] })]);
+  assert_eq!(res.events[0].ty, "escrow_lock");
+  let escrow = query_escrow(deps.as_ref(), "collection1".to_string(), "Test.1".to_string()).unwrap().escrow.unwrap();
+  assert_eq!(escrow.payment.unwrap().payer, "receiver1".to_string());
+
+  //the paid escrow is no longer for sale, so only the other one sets the floor
+  let floor = query_floor_price(deps.as_ref(), env.clone(), "collection1".to_string(), None, None).unwrap();
+  assert_eq!(floor.escrow.unwrap().token_id, "Test.2");
+
+  //a paid escrow can not be paid again or have its terms changed
+  let err = execute(deps.as_mut(), env.clone(), mock_info("receiver1", &[ujuno(100)]), approve("Test.1")).unwrap_err();
+  assert!(matches!(err, ContractError::AlreadyPaid {}));
+  let update = ExecuteMsg::UpdateEscrow {
+    collection: "collection1".to_string(),
+    token_id: "Test.1".to_string(),
+    recipient: None,
+    price: Some(vec![ujuno(10)]),
+    expiration: None
+  };
+  let err = execute(deps.as_mut(), env.clone(), mock_info("source1", &[]), update).unwrap_err();
+  assert!(matches!(err, ContractError::AlreadyPaid {}));
+
+  let err = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), release("Test.1")).unwrap_err();
+  assert!(matches!(err, ContractError::ConditionNotMet {}));
+
+  //once the oracle agrees anyone can release, paying the source and delivering the NFT
+  mock_oracle(&mut deps, true, "receiver1");
+  let res = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), release("Test.1")).unwrap();
+  assert_eq!(res.messages.len(), 2);
+  assert_eq!(res.messages[0], SubMsg::new(BankMsg::Send { to_address: "source1".to_string(), amount: vec![ujuno(100)] }));
+  assert_eq!(res.messages[1], SubMsg::reply_on_success(transfer_nft("collection1", "receiver1", "Test.1"), settle_reply_id(1)));
+  assert!(res.attributes.contains(&attr("payer", "receiver1")));
+  settle_reply(deps.as_mut(), env.clone()).unwrap();
+
+  //a condition that already holds settles right away on approve
+  let res = execute(deps.as_mut(), env.clone(), mock_info("receiver1", &[ujuno(100)]), approve("Test.2")).unwrap();
+  assert_eq!(res.messages.len(), 2);
+  settle_reply(deps.as_mut(), env.clone()).unwrap();
+
+  //a payment still locked at expiry goes back to the payer on withdraw
+  send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "source1".to_string(), "Test.3".to_string(), &sell_msg).unwrap();
+  mock_oracle(&mut deps, false, "receiver1");
+  execute(deps.as_mut(), env.clone(), mock_info("receiver1", &[ujuno(100)]), approve("Test.3")).unwrap();
+  env.block.time = env.block.time.plus_seconds(300);
+  let err = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), release("Test.3")).unwrap_err();
+  assert!(matches!(err, ContractError::EscrowExpired {}));
+  let msg = ExecuteMsg::WithdrawNft { collection: "collection1".to_string(), token_id: "Test.3".to_string() };
+  let res = execute(deps.as_mut(), env, mock_info("source1", &[]), msg).unwrap();
+  assert_eq!(res.messages.len(), 2);
+  assert_eq!(res.messages[1], SubMsg::new(BankMsg::Send { to_address: "receiver1".to_string(), amount: vec![ujuno(100)] }));
+}
+
+
+#[test]
+fn milestone_escrow() {
+  let mut deps = mock_dependencies();
+  let env = mock_env();
+
+  //init contract
+  setup_contract(deps.as_mut());
+
+  let milestone = |amount: u128| Milestone { amount: Uint128::new(amount), description: Binary::from(b"sketch".as_slice()), status: MilestoneStatus::Pending };
+  let mut sell_msg = EscrowInfoMsg{
+    recipient: "buyer".to_string(),
+    price: vec![ujuno(100)],
+    expiration: Timestamp::from_seconds(env.block.time.seconds() + 300),
+    payees: vec![],
+    kind: EscrowKind::Milestones { arbiter: "arbiter".to_string(), milestones: vec![milestone(30), milestone(60)] },
+    condition: None,
+  };
+  let err = send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "artist".to_string(), "Test.1".to_string(), &sell_msg).unwrap_err();
+  assert!(matches!(err, ContractError::InvalidMilestones { .. }));
+
+  sell_msg.kind = EscrowKind::Milestones { arbiter: "arbiter".to_string(), milestones: vec![milestone(30), milestone(70)] };
+  send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "artist".to_string(), "Test.1".to_string(), &sell_msg).unwrap();
+  send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "artist".to_string(), "Test.2".to_string(), &sell_msg).unwrap();
+
+  //commissions are not listings, they stay out of the floor price
+  let floor = query_floor_price(deps.as_ref(), env.clone(), "collection1".to_string(), None, None).unwrap();
+  assert_eq!(floor.escrow, None);
+
+  let submit = |token_id: &str, milestone: u32| ExecuteMsg::SubmitMilestone { collection: "collection1".to_string(), token_id: token_id.to_string(), milestone, work: Binary::from(b"work".as_slice()) };
+  let approve = |token_id: &str, milestone: u32| ExecuteMsg::ApproveMilestone { collection: "collection1".to_string(), token_id: token_id.to_string(), milestone };
+  let dispute = |token_id: &str, milestone: u32| ExecuteMsg::DisputeMilestone { collection: "collection1".to_string(), token_id: token_id.to_string(), milestone };
+  let resolve = |token_id: &str, milestone: u32, release: bool| ExecuteMsg::ResolveDispute { collection: "collection1".to_string(), token_id: token_id.to_string(), milestone, release };
+
+  //the work starts once the buyer has paid
+  let err = execute(deps.as_mut(), env.clone(), mock_info("artist", &[]), submit("Test.1", 0)).unwrap_err();
+  assert!(matches!(err, ContractError::NotPaid {}));
+  for token_id in ["Test.1", "Test.2"] {
+    let msg = ExecuteMsg::Approve { collection: "collection1".to_string(), token_id: token_id.to_string(), deliver_to: None, deliver_msg: None };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("buyer", &[ujuno(100)]), msg).unwrap();
+    assert!(res.messages.is_empty());
+  }
+  let release = ExecuteMsg::Release { collection: "collection1".to_string(), token_id: "Test.1".to_string() };
+  let err = execute(deps.as_mut(), env.clone(), mock_info("buyer", &[]), release).unwrap_err();
+  assert!(matches!(err, ContractError::WrongEscrowKind {}));
+
+  //only submitted work can be approved
+  let err = execute(deps.as_mut(), env.clone(), mock_info("buyer", &[]), approve("Test.1", 0)).unwrap_err();
+  assert!(matches!(err, ContractError::MilestoneState { index: 0, .. }));
+  let err = execute(deps.as_mut(), env.clone(), mock_info("buyer", &[]), submit("Test.1", 0)).unwrap_err();
+  assert!(matches!(err, ContractError::Unauthorized {}));
+  let err = execute(deps.as_mut(), env.clone(), mock_info("artist", &[]), submit("Test.1", 2)).unwrap_err();
+  assert!(matches!(err, ContractError::NoMilestone { index: 2 }));
+
+  //each approved milestone pays its tranche
+  execute(deps.as_mut(), env.clone(), mock_info("artist", &[]), submit("Test.1", 0)).unwrap();
+  let err = execute(deps.as_mut(), env.clone(), mock_info("artist", &[]), approve("Test.1", 0)).unwrap_err();
+  assert!(matches!(err, ContractError::Unauthorized {}));
+  let res = execute(deps.as_mut(), env.clone(), mock_info("buyer", &[]), approve("Test.1", 0)).unwrap();
+  assert_eq!(res.messages, vec![SubMsg::new(BankMsg::Send { to_address: "artist".to_string(), amount: vec![ujuno(30)] })]);
+  assert!(res.events[0].attributes.contains(&attr("status", "released")));
+
+  //a disputed milestone waits for the arbiter
+  execute(deps.as_mut(), env.clone(), mock_info("artist", &[]), submit("Test.1", 1)).unwrap();
+  execute(deps.as_mut(), env.clone(), mock_info("buyer", &[]), dispute("Test.1", 1)).unwrap();
+  let err = execute(deps.as_mut(), env.clone(), mock_info("buyer", &[]), approve("Test.1", 1)).unwrap_err();
+  assert!(matches!(err, ContractError::MilestoneState { index: 1, .. }));
+  let err = execute(deps.as_mut(), env.clone(), mock_info("buyer", &[]), resolve("Test.1", 1, false)).unwrap_err();
+  assert!(matches!(err, ContractError::Unauthorized {}));
+
+  //releasing the last milestone pays it and delivers the NFT
+  let res = execute(deps.as_mut(), env.clone(), mock_info("arbiter", &[]), resolve("Test.1", 1, true)).unwrap();
+  assert_eq!(res.messages.len(), 2);
+  assert_eq!(res.messages[0], SubMsg::reply_on_success(transfer_nft("collection1", "buyer", "Test.1"), settle_reply_id(1)));
+  assert_eq!(res.messages[1], SubMsg::new(BankMsg::Send { to_address: "artist".to_string(), amount: vec![ujuno(70)] }));
+  mock_nft_owner(&mut deps, "buyer");
+  settle_reply(deps.as_mut(), env.clone()).unwrap();
+  let stats = query_collection_stats(deps.as_ref(), "collection1".to_string()).unwrap();
+  assert_eq!(stats.last_sale, vec![ujuno(100)]);
+
+  //ruling for the buyer refunds what is left and returns the NFT
+  execute(deps.as_mut(), env.clone(), mock_info("artist", &[]), submit("Test.2", 0)).unwrap();
+  execute(deps.as_mut(), env.clone(), mock_info("buyer", &[]), approve("Test.2", 0)).unwrap();
+  execute(deps.as_mut(), env.clone(), mock_info("artist", &[]), dispute("Test.2", 1)).unwrap();
+  let res = execute(deps.as_mut(), env.clone(), mock_info("arbiter", &[]), resolve("Test.2", 1, false)).unwrap();
+  assert_eq!(res.messages.len(), 2);
+  assert_eq!(res.messages[0], SubMsg::new(transfer_nft("collection1", "artist", "Test.2")));
+  assert_eq!(res.messages[1], SubMsg::new(BankMsg::Send { to_address: "buyer".to_string(), amount: vec![ujuno(70)] }));
+  assert_eq!(query_escrow(deps.as_ref(), "collection1".to_string(), "Test.2".to_string()).unwrap().escrow, None);
+}
+
+
+#[test]
+fn buyer_offers() {
+  let mut deps = mock_dependencies();
+  let mut env = mock_env();
+
+  //init contract
+  setup_contract(deps.as_mut());
+
+  let make_offer = |token_id: &str, expiration: Timestamp| ExecuteMsg::MakeOffer { collection: "collection1".to_string(), token_id: token_id.to_string(), expiration };
+  let expiration = env.block.time.plus_seconds(300);
+
+  //offers need funds and a future expiry
+  let err = execute(deps.as_mut(), env.clone(), mock_info("bidder1", &[]), make_offer("Test.1", expiration)).unwrap_err();
+  assert!(matches!(err, ContractError::NotEnoughFunds {}));
+  let err = execute(deps.as_mut(), env.clone(), mock_info("bidder1", &[ujuno(100)]), make_offer("Test.1", env.block.time)).unwrap_err();
+  assert!(matches!(err, ContractError::OfferExpired {}));
+
+  execute(deps.as_mut(), env.clone(), mock_info("bidder1", &[ujuno(100)]), make_offer("Test.1", expiration)).unwrap();
+  execute(deps.as_mut(), env.clone(), mock_info("bidder2", &[ujuno(120)]), make_offer("Test.1", expiration)).unwrap();
+  execute(deps.as_mut(), env.clone(), mock_info("bidder1", &[ujuno(50)]), make_offer("Test.2", expiration)).unwrap();
+
+  let res = query_offers_by_token(deps.as_ref(), "collection1".to_string(), "Test.1".to_string(), None, None).unwrap();
+  assert_eq!(res.offers.iter().map(|offer| offer.id).collect::<Vec<_>>(), vec![1, 2]);
+  let res = query_offers_by_bidder(deps.as_ref(), "bidder1".to_string(), None, None).unwrap();
+  assert_eq!(res.offers.iter().map(|offer| offer.id).collect::<Vec<_>>(), vec![1, 3]);
+  let res = query_offers_by_bidder(deps.as_ref(), "bidder1".to_string(), Some(1), None).unwrap();
+  assert_eq!(res.offers.iter().map(|offer| offer.id).collect::<Vec<_>>(), vec![3]);
+
+  //the offer only takes the NFT it was made for
+  let accept = ReceiveNftMsg::AcceptOffer { offer_id: 2 };
+  let err = send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "owner1".to_string(), "Test.2".to_string(), &accept).unwrap_err();
+  assert!(matches!(err, ContractError::OfferMismatch {}));
+  let err = send_escrow_msg(deps.as_mut(), env.clone(), "collection2", "owner1".to_string(), "Test.1".to_string(), &accept).unwrap_err();
+  assert!(matches!(err, ContractError::OfferMismatch {}));
+
+  //accepting pays the owner and delivers the NFT to the bidder
+  let res = send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "owner1".to_string(), "Test.1".to_string(), &accept).unwrap();
+  assert_eq!(res.messages.len(), 2);
+  assert_eq!(res.messages[0], SubMsg::new(BankMsg::Send { to_address: "owner1".to_string(), amount: vec![ujuno(120)] }));
+  assert_eq!(res.messages[1], SubMsg::reply_on_success(transfer_nft("collection1", "bidder2", "Test.1"), settle_reply_id(1)));
+  assert_eq!(query_offer(deps.as_ref(), 2).unwrap().offer, None);
+  mock_nft_owner(&mut deps, "bidder2");
+  settle_reply(deps.as_mut(), env.clone()).unwrap();
+  let stats = query_collection_stats(deps.as_ref(), "collection1".to_string()).unwrap();
+  assert_eq!(stats.last_sale, vec![ujuno(120)]);
+
+  //the funds stay locked until the offer expires
+  let withdraw = |offer_id: u64| ExecuteMsg::WithdrawOffer { offer_id };
+  let err = execute(deps.as_mut(), env.clone(), mock_info("bidder1", &[]), withdraw(1)).unwrap_err();
+  assert!(matches!(err, ContractError::OfferNotExpired {}));
+
+  env.block.time = expiration;
+  let err = send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "owner2".to_string(), "Test.2".to_string(), &ReceiveNftMsg::AcceptOffer { offer_id: 3 }).unwrap_err();
+  assert!(matches!(err, ContractError::OfferExpired {}));
+  let err = execute(deps.as_mut(), env.clone(), mock_info("bidder2", &[]), withdraw(1)).unwrap_err();
+  assert!(matches!(err, ContractError::Unauthorized {}));
+  let res = execute(deps.as_mut(), env.clone(), mock_info("bidder1", &[]), withdraw(1)).unwrap();
+  assert_eq!(res.messages, vec![SubMsg::new(BankMsg::Send { to_address: "bidder1".to_string(), amount: vec![ujuno(100)] })]);
+  let err = execute(deps.as_mut(), env, mock_info("bidder1", &[]), withdraw(1)).unwrap_err();
+  assert!(matches!(err, ContractError::NoOffer {}));
+  let res = query_offers_by_token(deps.as_ref(), "collection1".to_string(), "Test.1".to_string(), None, None).unwrap();
+  assert!(res.offers.is_empty());
+}
+
+
+#[test]
+fn collection_offers() {
+  let mut deps = mock_dependencies();
+  let mut env = mock_env();
+
+  //init contract
+  setup_contract(deps.as_mut());
+
+  let expiration = env.block.time.plus_seconds(300);
+  let make_offer = |price: u128, quantity: Option<u32>| ExecuteMsg::MakeCollectionOffer { collection: "collection1".to_string(), price: ujuno(price), quantity, expiration };
+
+  //every unit has to be funded
+  let err = execute(deps.as_mut(), env.clone(), mock_info("bidder1", &[ujuno(100)]), make_offer(50, Some(0))).unwrap_err();
+  assert!(matches!(err, ContractError::InvalidQuantity {}));
+  let err = execute(deps.as_mut(), env.clone(), mock_info("bidder1", &[ujuno(100)]), make_offer(50, Some(3))).unwrap_err();
+  assert!(matches!(err, ContractError::Underpayment { .. }));
+
+  let res = execute(deps.as_mut(), env.clone(), mock_info("bidder1", &[ujuno(110)]), make_offer(50, Some(2))).unwrap();
+  assert_eq!(res.messages, vec![SubMsg::new(BankMsg::Send { to_address: "bidder1".to_string(), amount: vec![ujuno(10)] })]);
+  execute(deps.as_mut(), env.clone(), mock_info("bidder2", &[ujuno(60)]), make_offer(60, None)).unwrap();
+  execute(deps.as_mut(), env.clone(), mock_info("bidder3", &[ujuno(50)]), make_offer(50, None)).unwrap();
+  execute(deps.as_mut(), env.clone(), mock_info("bidder3", &[ujuno(40)]), make_offer(40, None)).unwrap();
+
+  //highest price first, older offers first on a tie
+  let best = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>, env: &Env, start_after: Option<CollectionOfferOffset>, limit: Option<u32>| -> Vec<u64> {
+    query_collection_offers(deps.as_ref(), env.clone(), "collection1".to_string(), None, start_after, limit).unwrap()
+      .offers.iter().map(|offer| offer.id).collect()
+  };
+  assert_eq!(best(&deps, &env, None, None), vec![2, 1, 3, 4]);
+  assert_eq!(best(&deps, &env, None, Some(2)), vec![2, 1]);
+  assert_eq!(best(&deps, &env, Some(CollectionOfferOffset { price: Uint128::new(50), offer_id: 1 }), None), vec![3, 4]);
+  let res = query_collection_offers(deps.as_ref(), env.clone(), "collection1".to_string(), Some("uatom".to_string()), None, None).unwrap();
+  assert!(res.offers.is_empty());
+  let res = query_collection_offers_by_bidder(deps.as_ref(), "bidder3".to_string(), None, None).unwrap();
+  assert_eq!(res.offers.iter().map(|offer| offer.id).collect::<Vec<_>>(), vec![3, 4]);
+
+  //any NFT of the collection fills a unit
+  let fill = ReceiveNftMsg::FillCollectionOffer { offer_id: 1 };
+  let err = send_escrow_msg(deps.as_mut(), env.clone(), "collection2", "owner1".to_string(), "Test.1".to_string(), &fill).unwrap_err();
+  assert!(matches!(err, ContractError::OfferMismatch {}));
+  let res = send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "owner1".to_string(), "Test.1".to_string(), &fill).unwrap();
+  assert_eq!(res.messages.len(), 2);
+  assert_eq!(res.messages[0], SubMsg::new(BankMsg::Send { to_address: "owner1".to_string(), amount: vec![ujuno(50)] }));
+  assert_eq!(res.messages[1], SubMsg::reply_on_success(transfer_nft("collection1", "bidder1", "Test.1"), settle_reply_id(1)));
+  assert_eq!(query_collection_offer(deps.as_ref(), 1).unwrap().offer.unwrap().quantity, 1);
+  mock_nft_owner(&mut deps, "bidder1");
+  settle_reply(deps.as_mut(), env.clone()).unwrap();
+
+  //the last unit closes the offer
+  send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "owner2".to_string(), "Test.2".to_string(), &fill).unwrap();
+  settle_reply(deps.as_mut(), env.clone()).unwrap();
+  assert_eq!(query_collection_offer(deps.as_ref(), 1).unwrap().offer, None);
+  assert_eq!(best(&deps, &env, None, None), vec![2, 3, 4]);
+  let err = send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "owner3".to_string(), "Test.3".to_string(), &fill).unwrap_err();
+  assert!(matches!(err, ContractError::NoOffer {}));
+
+  //expired offers drop out of the list and their funds go back
+  let withdraw = ExecuteMsg::WithdrawCollectionOffer { offer_id: 2 };
+  let err = execute(deps.as_mut(), env.clone(), mock_info("bidder2", &[]), withdraw.clone()).unwrap_err();
+  assert!(matches!(err, ContractError::OfferNotExpired {}));
+  env.block.time = expiration;
+  assert!(best(&deps, &env, None, None).is_empty());
+  let err = send_escrow_msg(deps.as_mut(), env.clone(), "collection1", "owner3".to_string(), "Test.3".to_string(), &ReceiveNftMsg::FillCollectionOffer { offer_id: 2 }).unwrap_err();
+  assert!(matches!(err, ContractError::OfferExpired {}));
+  let res = execute(deps.as_mut(), env, mock_info("bidder2", &[]), withdraw).unwrap();
+  assert_eq!(res.messages, vec![SubMsg::new(BankMsg::Send { to_address: "bidder2".to_string(), amount: vec![ujuno(60)] })]);
+}
+
+
+#[test]
+fn change_config_admin_only() {
+  let mut deps = mock_dependencies();
+
+  //init contract
+  setup_contract(deps.as_mut());
+
+  //naming yourself admin in the new config must not pass the check
+  let msg = ExecuteMsg::ChangeConfig { state: State { admin: "attacker".to_string(), denom: "ujuno".to_string() } };
+  let err = execute(deps.as_mut(), mock_env(), mock_info("attacker", &[]), msg).unwrap_err();
+  assert!(matches!(err, ContractError::Unauthorized {}));
+  assert_eq!(query_state_info(deps.as_ref()).unwrap().admin, "admin".to_string());
+
+  let msg = ExecuteMsg::ChangeConfig { state: State { admin: "admin2".to_string(), denom: "uatom".to_string() } };
+  execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+  let state = query_state_info(deps.as_ref()).unwrap();
+  assert_eq!(state.admin, "admin2".to_string());
+  assert_eq!(state.denom, "uatom".to_string());
+}
+
+
+#[test]
+fn nested_settlements() {
+  let mut deps = mock_dependencies();
+  let env = mock_env();
+
+  //init contract
+  setup_contract(deps.as_mut());
+
+  for token_id in ["Test.1", "Test.2"] {
+    send_nft(deps.as_mut(), env.clone(), "collection1", "source1".to_string(), token_id.to_string(), "receiver1".to_string(), Uint128::new(50));
+  }
+
+  //the second approve runs inside the first delivery, before its reply
+  let approve = |token_id: &str| ExecuteMsg::Approve { collection: "collection1".to_string(), token_id: token_id.to_string(), deliver_to: None, deliver_msg: None };
+  let outer = execute(deps.as_mut(), env.clone(), mock_info("receiver1", &[ujuno(50)]), approve("Test.1")).unwrap();
+  let inner = execute(deps.as_mut(), env.clone(), mock_info("receiver1", &[ujuno(50)]), approve("Test.2")).unwrap();
+  assert_eq!(outer.messages[1].id, settle_reply_id(1));
+  assert_eq!(inner.messages[1].id, settle_reply_id(2));
+
+  //replies come back innermost first and each finds its own settlement
+  mock_nft_owner(&mut deps, "receiver1");
+  let res = settle_reply_for(deps.as_mut(), env.clone(), 2).unwrap();
+  assert!(res.events[0].attributes.contains(&attr("token_id", "Test.2")));
+  let res = settle_reply_for(deps.as_mut(), env.clone(), 1).unwrap();
+  assert!(res.events[0].attributes.contains(&attr("token_id", "Test.1")));
+
+  let stats = query_contract_stats(deps.as_ref()).unwrap();
+  assert_eq!(stats.settlements, 2);
+  assert!(settle_reply_for(deps.as_mut(), env, 1).is_err());
+}